@@ -1,14 +1,16 @@
 use std::error::Error;
 use std::fs;
 use std::fs::File;
+use std::io;
 use std::io::Write;
-use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+use clap::{Parser, Subcommand, ValueEnum};
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 use log::{info, trace};
-use crate::rsa::{Key, KeySet};
+use crate::rsa::{jwk_set, write_key_file, KeyGenOptions, DEFAULT_PUBLIC_EXPONENT, DEFAULT_SALT_BITS, HashAlgorithm, Key, KeyFormat, KeySet, Padding, SignatureScheme};
 use num::BigUint;
-use rayon::prelude::*;
+use rand::{CryptoRng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
 
 #[derive(Parser)]
 #[command(name = "rsa")]
@@ -16,6 +18,9 @@ use rayon::prelude::*;
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+    /// Directory of the key store, overrides AMH_RSA_KEY_DIR and the default of ~/.amh_rsa
+    #[arg(long, global = true)]
+    pub key_dir: Option<PathBuf>,
     #[command(flatten)]
     pub(crate) verbose: clap_verbosity_flag::Verbosity
 }
@@ -24,15 +29,40 @@ pub struct Cli {
 pub enum Commands {
     /// Generate RSA key pair
     Keygen {
-        /// The name to save the public and private keys under
+        /// The name to save the public and private keys under, a value containing a path separator is refused
         #[arg(short, long)]
         key_name: String,
         /// The number of bits of salting to use, defaults to 6
-        #[arg(short, long, default_value_t = 6)]
+        #[arg(short, long, default_value_t = DEFAULT_SALT_BITS)]
         salt_bits: u32,
         /// The key bit length to use, defaults to 4096
         #[arg(short, long, default_value_t = 4096)]
-        bit_length: u64
+        bit_length: u64,
+        /// The public exponent, any odd number of at least 3, defaults to 65537
+        #[arg(short = 'e', long, default_value_t = BigUint::from(DEFAULT_PUBLIC_EXPONENT))]
+        public_exponent: BigUint,
+        /// Apply the prime checks of FIPS 186-5 Appendix A.1.3. The random numbers do not come from an
+        /// SP 800-90A DRBG as the standard requires, so the key is not FIPS compliant
+        #[arg(long, conflicts_with_all = ["seed", "seed_passphrase"])]
+        fips: bool,
+        /// Use provable primes and print their primality certificates
+        #[arg(long, conflicts_with = "fips")]
+        provable: bool,
+        /// Generate the key pair deterministically from a seed of at least 16 bytes given in hex
+        #[arg(long)]
+        seed: Option<String>,
+        /// Generate the key pair deterministically from a passphrase, prompted for when no value is given
+        #[arg(long, num_args = 0..=1, default_missing_value = "", conflicts_with = "seed")]
+        seed_passphrase: Option<String>,
+        /// The format to store the keys in, defaults to the native format
+        #[arg(short, long, value_enum, default_value_t = KeyFormat::Native)]
+        format: KeyFormat,
+        /// Protect the private key with a passphrase, prompted for when no value is given
+        #[arg(long, num_args = 0..=1, default_missing_value = "")]
+        passphrase: Option<String>,
+        /// Overwrite an existing key with the same name
+        #[arg(long)]
+        force: bool
     },
     /// Encrypt a file
     Encrypt {
@@ -42,9 +72,18 @@ pub enum Commands {
         /// Output file for the encrypted data (if not provided, print to stdout)
         #[arg(short, long)]
         output: Option<PathBuf>,
-        /// Public key name to use for encryption
+        /// Public key name to use for encryption, a value containing a path separator is read as a file path
         #[arg(short, long)]
         key_name: String,
+        /// Wrap a random AES-256-GCM key with RSA instead of encrypting the data directly
+        #[arg(long)]
+        hybrid: bool,
+        /// Padding scheme for the RSA blocks, defaults to OAEP
+        #[arg(short, long, value_enum, default_value_t = PaddingScheme::Oaep)]
+        padding: PaddingScheme,
+        /// Optional OAEP label, must match between encryption and decryption
+        #[arg(long)]
+        label: Option<String>,
     },
     /// Decrypt a file
     Decrypt {
@@ -54,85 +93,556 @@ pub enum Commands {
         /// Output file for the decrypted data (if not provided, print to stdout)
         #[arg(short, long)]
         output: Option<PathBuf>,
-        /// Private key name to use for decryption
+        /// Private key name to use for decryption, a value containing a path separator is read as a file path
         #[arg(short, long)]
         key_name: String,
+        /// Passphrase of the private key, prompted for when the key is protected and none is given
+        #[arg(long)]
+        passphrase: Option<String>,
+        /// Decrypt a file produced with encrypt --hybrid
+        #[arg(long)]
+        hybrid: bool,
+        /// Padding scheme for the RSA blocks, defaults to OAEP
+        #[arg(short, long, value_enum, default_value_t = PaddingScheme::Oaep)]
+        padding: PaddingScheme,
+        /// Optional OAEP label, must match between encryption and decryption
+        #[arg(long)]
+        label: Option<String>,
     },
+    /// Create a detached signature for a file
+    Sign {
+        /// Input file to sign
+        #[arg(short, long)]
+        input: PathBuf,
+        /// Output file for the signature, defaults to the input path with .sig appended
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Private key name to sign with, a value containing a path separator is read as a file path
+        #[arg(short, long)]
+        key_name: String,
+        /// Passphrase of the private key, prompted for when the key is protected and none is given
+        #[arg(long)]
+        passphrase: Option<String>,
+        /// Signature scheme to use, defaults to PSS
+        #[arg(long, value_enum, default_value_t = SignatureKind::Pss)]
+        scheme: SignatureKind,
+        /// The PSS salt length in bytes, defaults to 32
+        #[arg(short, long, default_value_t = 32)]
+        salt_length: usize,
+        /// Hash algorithm for PKCS#1 v1.5 signatures, PSS always uses SHA-256
+        #[arg(long, value_enum, default_value_t = HashAlgorithm::Sha256)]
+        hash: HashAlgorithm
+    },
+    /// Verify a detached signature
+    Verify {
+        /// Input file the signature was made over
+        #[arg(short, long)]
+        input: PathBuf,
+        /// Signature file, defaults to the input path with .sig appended
+        #[arg(long)]
+        signature: Option<PathBuf>,
+        /// Public key name to verify against, a value containing a path separator is read as a file path
+        #[arg(short, long)]
+        key_name: String,
+        /// Signature scheme to use, defaults to PSS
+        #[arg(long, value_enum, default_value_t = SignatureKind::Pss)]
+        scheme: SignatureKind,
+        /// The PSS salt length in bytes, defaults to 32
+        #[arg(short, long, default_value_t = 32)]
+        salt_length: usize,
+        /// Hash algorithm for PKCS#1 v1.5 signatures, PSS always uses SHA-256
+        #[arg(long, value_enum, default_value_t = HashAlgorithm::Sha256)]
+        hash: HashAlgorithm
+    },
+    /// Export a stored key in a standard format
+    Export {
+        /// Name of the stored key to export, a value containing a path separator is read as a file path
+        #[arg(short, long)]
+        key_name: String,
+        /// Output format, defaults to PKCS#8 PEM
+        #[arg(short, long, value_enum, default_value_t = KeyFormat::Pkcs8Pem)]
+        format: KeyFormat,
+        /// Export the private key instead of the public key
+        #[arg(long)]
+        private: bool,
+        /// Passphrase of the private key, prompted for when the key is protected and none is given
+        #[arg(long)]
+        passphrase: Option<String>,
+        /// Export the private key as PBES2 encrypted PKCS#8, prompted for when no value is given
+        #[arg(long, num_args = 0..=1, default_missing_value = "", requires = "private")]
+        export_passphrase: Option<String>,
+        /// Output file for the exported key (if not provided, print to stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Overwrite an existing output file
+        #[arg(long)]
+        force: bool
+    },
+    /// Import a key generated elsewhere into the key store
+    Import {
+        /// Input file containing a public or private key
+        #[arg(short, long)]
+        input: PathBuf,
+        /// The name to save the imported key under, a value containing a path separator is refused
+        #[arg(short, long)]
+        key_name: String,
+        /// The number of bits of salting to use with the legacy padding, defaults to 6
+        #[arg(short, long, default_value_t = DEFAULT_SALT_BITS)]
+        salt_bits: u32,
+        /// Passphrase of an encrypted key, prompted for when the key is protected and none is given
+        #[arg(long)]
+        passphrase: Option<String>,
+        /// Overwrite an existing key with the same name
+        #[arg(long)]
+        force: bool
+    },
+    /// Add, change or remove the passphrase protecting a private key
+    Passphrase {
+        /// Name of the private key in the key store, a value containing a path separator is refused
+        #[arg(short, long)]
+        key_name: String,
+        /// Passphrase of the private key, prompted for when the key is protected and none is given
+        #[arg(long)]
+        passphrase: Option<String>,
+        /// The new passphrase, prompted for when not given
+        #[arg(long)]
+        new_passphrase: Option<String>,
+        /// Store the private key without a passphrase
+        #[arg(long, conflicts_with = "new_passphrase")]
+        remove: bool
+    },
+    /// Show the parameters and fingerprint of a stored key
+    Inspect {
+        /// Name of the stored key to inspect, a value containing a path separator is read as a file path
+        #[arg(short, long)]
+        key_name: String,
+        /// Inspect the private key instead of the public key
+        #[arg(long)]
+        private: bool,
+        /// Passphrase of the private key, prompted for when the key is protected and none is given
+        #[arg(long)]
+        passphrase: Option<String>
+    },
+    /// List the key pairs in the key store
+    List,
+    /// Delete a key pair from the key store
+    Delete {
+        /// Name of the stored key to delete
+        #[arg(short, long)]
+        key_name: String,
+        /// Delete without asking for confirmation
+        #[arg(short, long)]
+        yes: bool
+    },
+    /// Rename a key pair in the key store
+    Rename {
+        /// Name of the stored key to rename
+        #[arg(short, long)]
+        key_name: String,
+        /// The new name, which must not be in use
+        #[arg(short, long)]
+        new_name: String
+    },
+    /// Copy a key pair to a new name in the key store
+    Copy {
+        /// Name of the stored key to copy
+        #[arg(short, long)]
+        key_name: String,
+        /// The name of the copy, which must not be in use
+        #[arg(short, long)]
+        new_name: String
+    },
+    /// Publish the public keys of stored key pairs as a JWK Set
+    Jwks {
+        /// Names of the stored keys to include, may be given multiple times, a value containing a path separator is read as a file path
+        #[arg(short, long, required = true)]
+        key_name: Vec<String>,
+        /// Output file for the JWK Set (if not provided, print to stdout)
+        #[arg(short, long)]
+        output: Option<PathBuf>
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum PaddingScheme {
+    /// RSAES-OAEP with SHA-256 and MGF1
+    Oaep,
+    /// The legacy scheme of a few random salt bits per block
+    Salted,
+    /// RSAES-PKCS1-v1_5 for interoperability with OpenSSL and older systems
+    Pkcs1v15
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum SignatureKind {
+    /// RSASSA-PSS with SHA-256 and MGF1
+    Pss,
+    /// Deterministic RSASSA-PKCS1-v1_5
+    Pkcs1v15
+}
+
+impl SignatureKind {
+    fn scheme(&self, salt_length: usize, hash: HashAlgorithm) -> SignatureScheme {
+        match self {
+            SignatureKind::Pss => SignatureScheme::Pss { salt_length },
+            SignatureKind::Pkcs1v15 => SignatureScheme::Pkcs1v15 { hash }
+        }
+    }
+}
+
+impl PaddingScheme {
+    fn padding(&self, label: &Option<String>) -> Padding {
+        match self {
+            PaddingScheme::Oaep => {
+                Padding::Oaep {
+                    label: label.clone().unwrap_or_default().into_bytes()
+                }
+            }
+            PaddingScheme::Salted => Padding::Salted,
+            PaddingScheme::Pkcs1v15 => Padding::Pkcs1v15
+        }
+    }
 }
 
 impl Commands {
     pub fn execute(&self) -> Result<(), Box<dyn Error>> {
         match self {
-            Commands::Keygen {key_name, salt_bits, bit_length} => {
+            Commands::Keygen {key_name, salt_bits, bit_length, public_exponent, fips, provable, seed, seed_passphrase, format, passphrase, force} => {
+                if !*force && KeySet::key_exists(key_name)? {
+                    return Err(format!("A key named {} already exists, use --force to overwrite it", key_name).into());
+                }
+                let passphrase = match passphrase {
+                    Some(passphrase) => Some(new_passphrase(passphrase)?),
+                    None => None
+                };
+                let options = KeyGenOptions::new(*salt_bits, *bit_length)
+                    .with_public_exponent(public_exponent.clone())
+                    .with_provable_primes(*provable);
+                let mut rng = match (seed, seed_passphrase) {
+                    (Some(seed), _) => ChaCha20Rng::from_seed(KeyGenOptions::seed_from_hex(seed)?),
+                    (None, Some(seed_passphrase)) => {
+                        let seed_passphrase = new_passphrase(seed_passphrase)?;
+                        ChaCha20Rng::from_seed(KeyGenOptions::seed_from_passphrase(&seed_passphrase)?)
+                    }
+                    (None, None) => ChaCha20Rng::from_entropy()
+                };
                 let perf_start = Instant::now();
-                println!("Generating Keypair, this may take a moment...");
-                let keyset = KeySet::new(*salt_bits, *bit_length);
-                println!("Saving keys with name {}", key_name);
-                keyset.save_keys(key_name)?;
+                eprintln!("Generating Keypair, this may take a moment...");
+                let keyset = if *fips {
+                    let (keyset, checks) = KeySet::generate_fips(&mut rng, &options)?;
+                    println!("Applied FIPS 186-5 A.1.3 checks, the key is not FIPS compliant as no approved DRBG was used:");
+                    for check in checks {
+                        println!("  {}", check);
+                    }
+                    keyset
+                }
+                else {
+                    KeySet::generate(&mut rng, &options)?
+                };
+                if let Some((p_certificate, q_certificate)) = keyset.prime_certificates() {
+                    for (prime, certificate) in [("p", p_certificate), ("q", q_certificate)] {
+                        let status = if certificate.verify() { "verified" } else { "invalid" };
+                        println!("Certificate for {} ({}):\n{}", prime, status, certificate);
+                    }
+                }
+                info!("Generated a {} bit key", keyset.bit_length());
+                eprintln!("Saving keys with name {}", key_name);
+                // Salts and nonces of the key file stay random even when the key itself is seeded
+                keyset.save_keys_as(&mut rand::thread_rng(), key_name, *format, passphrase.as_deref(), *force)?;
                 info!("Key generation took {:?}", perf_start.elapsed());
                 Ok(())
             }
-            Commands::Encrypt { input, output, key_name } => {
+            Commands::Encrypt { input, output, key_name, hybrid, padding, label } => {
                 let perf_start = Instant::now();
-                println!("Encrypting input file...");
+                eprintln!("Encrypting input file...");
                 let mut rng = rand::thread_rng();
                 let public_key = Key::load_public_key(key_name)?;
-                let plaintext = fs::read_to_string(input)?;
-                trace!("Encrypting input text: {}", &plaintext);
-                let ciphertext: Vec<BigUint> = plaintext
-                    .chars()
-                    .map(|i| {
-                        public_key.encrypt(&mut rng, i as u8)
-                    })
-                    .collect();
+                let padding = padding.padding(label);
+                let plaintext = fs::read(input)?;
+                trace!("Encrypting input bytes: {:?}", &plaintext);
+                let data = if *hybrid {
+                    public_key.encrypt_hybrid(&mut rng, &plaintext, &padding)?
+                }
+                else {
+                    public_key.encrypt(&mut rng, &plaintext, &padding)?
+                        .iter()
+                        .map(|i| {i.to_string()})
+                        .collect::<Vec<String>>()
+                        .join("\n")
+                        .into_bytes()
+                };
                 match output {
                     None => {
-                        for i in ciphertext {
-                            println!("{}", i);
+                        io::stdout().write_all(&data)?;
+                        if !*hybrid {
+                            println!();
                         }
                     }
                     Some(output) => {
-                        let data = ciphertext
-                            .iter()
-                            .map(|i| {i.to_string()})
-                            .collect::<Vec<String>>()
-                            .join("\n");
                         let mut file = File::create(output)?;
-                        file.write_all(data.as_bytes())?;
+                        file.write_all(&data)?;
                     }
                 }
                 info!("Encryption took {:?}", perf_start.elapsed());
                 Ok(())
             }
-            Commands::Decrypt { input, output, key_name } => {
+            Commands::Decrypt { input, output, key_name, passphrase, hybrid, padding, label } => {
                 let perf_start = Instant::now();
-                println!("Decrypting input file, this may take a while...");
-                let private_key = Key::load_private_key(key_name)?;
-                let ciphertext: Vec<BigUint> = fs::read_to_string(input)?
-                    .lines()
-                    .map(|i| {
-                        i.parse::<BigUint>().unwrap()
-                    })
-                    .collect();
-                trace!("Decrypting input text: {:?}", &ciphertext);
-                let plaintext: String = ciphertext
-                    .par_iter()
+                eprintln!("Decrypting input file, this may take a while...");
+                let private_key = load_private_key(&mut rand::thread_rng(), key_name, passphrase)?;
+                let padding = padding.padding(label);
+                let plaintext = if *hybrid {
+                    private_key.decrypt_hybrid(&fs::read(input)?, &padding)?
+                }
+                else {
+                    let ciphertext = fs::read_to_string(input)?
+                        .lines()
+                        .map(|i| {
+                            i.parse::<BigUint>()
+                        })
+                        .collect::<Result<Vec<BigUint>, _>>()?;
+                    trace!("Decrypting input text: {:?}", &ciphertext);
+                    private_key.decrypt(&ciphertext, &padding)?
+                };
+                match output {
+                    None => {
+                        io::stdout().write_all(&plaintext)?;
+                    }
+                    Some(output) => {
+                        let mut file = File::create(output)?;
+                        file.write_all(&plaintext)?;
+                    }
+                }
+                info!("Decryption took {:?}", perf_start.elapsed());
+                Ok(())
+            }
+            Commands::Sign { input, output, key_name, passphrase, scheme, salt_length, hash } => {
+                let perf_start = Instant::now();
+                let mut rng = rand::thread_rng();
+                let private_key = load_private_key(&mut rng, key_name, passphrase)?;
+                let message = fs::read(input)?;
+                let signature = private_key.sign(&mut rng, &message, &scheme.scheme(*salt_length, *hash))?;
+                let output = output.clone().unwrap_or_else(|| signature_path(input));
+                eprintln!("Writing signature to {}", output.display());
+                let mut file = File::create(output)?;
+                file.write_all(&signature)?;
+                info!("Signing took {:?}", perf_start.elapsed());
+                Ok(())
+            }
+            Commands::Verify { input, signature, key_name, scheme, salt_length, hash } => {
+                let perf_start = Instant::now();
+                let public_key = Key::load_public_key(key_name)?;
+                let message = fs::read(input)?;
+                let signature = fs::read(signature.clone().unwrap_or_else(|| signature_path(input)))?;
+                public_key.verify(&message, &signature, &scheme.scheme(*salt_length, *hash))?;
+                println!("Signature is valid");
+                info!("Verification took {:?}", perf_start.elapsed());
+                Ok(())
+            }
+            Commands::Export { key_name, format, private, passphrase, export_passphrase, output, force } => {
+                let data = if *private {
+                    let mut rng = rand::thread_rng();
+                    let keyset = KeySet::from_private_key(load_private_key(&mut rng, key_name, passphrase)?)?;
+                    match export_passphrase {
+                        Some(export_passphrase) => {
+                            let export_passphrase = new_passphrase(export_passphrase)?;
+                            keyset.export_encrypted_private(&mut rng, *format, &export_passphrase)?
+                        }
+                        None => keyset.export_private(&mut rng, *format)?
+                    }
+                }
+                else {
+                    Key::load_public_key(key_name)?.export_public(*format)?
+                };
+                match output {
+                    None => {
+                        io::stdout().write_all(&data)?;
+                    }
+                    Some(output) => {
+                        write_key_file(output, &data, *private, *force)?;
+                    }
+                }
+                Ok(())
+            }
+            Commands::Import { input, key_name, salt_bits, passphrase, force } => {
+                // Checked before any passphrase prompt, the imported key is always saved to the key store
+                KeySet::check_key_name(key_name)?;
+                let mut rng = rand::thread_rng();
+                let data = fs::read(input)?;
+                if Key::is_protected(&data) {
+                    // Encrypted keys stay protected by the same passphrase once imported
+                    let passphrase = match passphrase {
+                        Some(passphrase) => passphrase.clone(),
+                        None => rpassword::prompt_password(format!("Passphrase for {}: ", input.display()))?
+                    };
+                    let key = Key::unprotect(&data, &passphrase)?;
+                    println!("Importing encrypted private key as {}", key_name);
+                    let keyset = KeySet::from_private_key(key)?;
+                    keyset.validate(&mut rng)?;
+                    keyset.save_keys_as(&mut rng, key_name, KeyFormat::Native, Some(&passphrase), *force)?;
+                    return Ok(());
+                }
+                let key = Key::import(&data, *salt_bits)?;
+                if key.is_private() {
+                    println!("Importing private key as {}", key_name);
+                    let keyset = KeySet::from_private_key(key)?;
+                    keyset.validate(&mut rng)?;
+                    keyset.save_keys(&mut rng, key_name, *force)?;
+                }
+                else {
+                    println!("Importing public key as {}", key_name);
+                    key.save_public_key(key_name, *force)?;
+                }
+                Ok(())
+            }
+            Commands::Passphrase { key_name, passphrase, new_passphrase: replacement, remove } => {
+                // The key is written back to the key store, so a file path would leave the original unprotected
+                KeySet::check_key_name(key_name)?;
+                let mut rng = rand::thread_rng();
+                let private_key = load_private_key(&mut rng, key_name, passphrase)?;
+                if *remove {
+                    println!("Removing passphrase from {}", key_name);
+                    private_key.save_private_key(&mut rng, key_name, KeyFormat::Native, None, true)?;
+                }
+                else {
+                    let replacement = new_passphrase(replacement.as_deref().unwrap_or_default())?;
+                    println!("Updating passphrase of {}", key_name);
+                    private_key.save_private_key(&mut rng, key_name, KeyFormat::Native, Some(&replacement), true)?;
+                }
+                Ok(())
+            }
+            Commands::Inspect { key_name, private, passphrase } => {
+                let key = if *private {
+                    // Loaded without validation so that the report below can say what is wrong
+                    let passphrase = private_key_passphrase(key_name, passphrase)?;
+                    Key::load_unchecked_private_key(key_name, passphrase.as_deref())?
+                }
+                else {
+                    Key::load_public_key(key_name)?
+                };
+                let public_exponent = match key.public_exponent() {
+                    Some(public_exponent) if !*private || key.is_private() => public_exponent.to_string(),
+                    _ => "unknown".to_string()
+                };
+                println!("Key: {}", key_name);
+                println!("Type: {}", if *private { "private" } else { "public" });
+                println!("Modulus: {} bits", key.modulus_bits());
+                println!("Public exponent: {}", public_exponent);
+                println!("Salt bits: {}", key.salt_bits());
+                println!("Fingerprint: {}", key.fingerprint().unwrap_or_else(|_| "unknown".to_string()));
+                if *private {
+                    let consistent = match key.is_consistent() {
+                        Some(true) => "yes",
+                        Some(false) => "no",
+                        None => "unknown"
+                    };
+                    println!("Consistent: {}", consistent);
+                    let validation = match KeySet::from_private_key(key.clone()) {
+                        Ok(keyset) => match keyset.validate(&mut rand::thread_rng()) {
+                            Ok(()) => "passed".to_string(),
+                            Err(report) => report.to_string()
+                        },
+                        Err(error) => error.to_string()
+                    };
+                    println!("Validation: {}", validation);
+                }
+                Ok(())
+            }
+            Commands::List => {
+                for key in KeySet::list_keys()? {
+                    let halves = match (key.has_public, key.has_private) {
+                        (true, true) => "public, private",
+                        (true, false) => "public",
+                        (false, _) => "private"
+                    };
+                    println!("{} ({})", key.name, halves);
+                }
+                Ok(())
+            }
+            Commands::Delete { key_name, yes } => {
+                if !*yes && !confirm(&format!("Delete key {}?", key_name))? {
+                    return Err("Not deleting without confirmation".into());
+                }
+                KeySet::delete_keys(key_name)?;
+                println!("Deleted key {}", key_name);
+                Ok(())
+            }
+            Commands::Rename { key_name, new_name } => {
+                KeySet::rename_keys(key_name, new_name)?;
+                println!("Renamed key {} to {}", key_name, new_name);
+                Ok(())
+            }
+            Commands::Copy { key_name, new_name } => {
+                KeySet::copy_keys(key_name, new_name)?;
+                println!("Copied key {} to {}", key_name, new_name);
+                Ok(())
+            }
+            Commands::Jwks { key_name, output } => {
+                let keys = key_name
+                    .iter()
                     .map(|i| {
-                        private_key.decrypt(i).unwrap() as char
+                        Key::load_public_key(i)
                     })
-                    .collect::<String>();
+                    .collect::<Result<Vec<Key>, Box<dyn Error>>>()?;
+                let data = format!("{:#}\n", jwk_set(&keys)?);
                 match output {
                     None => {
-                        println!("{}", plaintext);
+                        io::stdout().write_all(data.as_bytes())?;
                     }
                     Some(output) => {
                         let mut file = File::create(output)?;
-                        file.write_all(plaintext.as_bytes())?;
+                        file.write_all(data.as_bytes())?;
                     }
                 }
-                info!("Decryption took {:?}", perf_start.elapsed());
                 Ok(())
             }
         }
     }
 }
+
+fn signature_path(input: &Path) -> PathBuf {
+    let mut path = input.as_os_str().to_owned();
+    path.push(".sig");
+    PathBuf::from(path)
+}
+
+fn load_private_key<R: RngCore + CryptoRng>(rng: &mut R, key_name: &str, passphrase: &Option<String>) -> Result<Key, Box<dyn Error>> {
+    match private_key_passphrase(key_name, passphrase)? {
+        Some(passphrase) => Key::load_private_key_with_passphrase(rng, key_name, Some(&passphrase)),
+        None => Key::load_private_key(rng, key_name)
+    }
+}
+
+// Prompts for the passphrase only when the key is protected and none was given
+fn private_key_passphrase(key_name: &str, passphrase: &Option<String>) -> Result<Option<String>, Box<dyn Error>> {
+    if !Key::is_private_key_protected(key_name)? {
+        return Ok(None);
+    }
+    match passphrase {
+        Some(passphrase) => Ok(Some(passphrase.clone())),
+        None => Ok(Some(rpassword::prompt_password(format!("Passphrase for {}: ", key_name))?))
+    }
+}
+
+fn confirm(prompt: &str) -> Result<bool, Box<dyn Error>> {
+    print!("{} [y/N] ", prompt);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+// An empty value means the passphrase should be prompted for and confirmed
+fn new_passphrase(passphrase: &str) -> Result<String, Box<dyn Error>> {
+    if !passphrase.is_empty() {
+        return Ok(passphrase.to_string());
+    }
+    let passphrase = rpassword::prompt_password("New passphrase: ")?;
+    if passphrase.is_empty() {
+        return Err("Passphrase must not be empty".into());
+    }
+    if rpassword::prompt_password("Confirm passphrase: ")? != passphrase {
+        return Err("Passphrases do not match".into());
+    }
+    Ok(passphrase)
+}
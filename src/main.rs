@@ -1,6 +1,3 @@
-#![feature(future_join)]
-extern crate core;
-
 mod rsa;
 mod math;
 mod cli;
@@ -8,18 +5,26 @@ mod cli;
 mod tests;
 
 use std::error::Error;
-use clap::builder::TypedValueParser;
+use std::process;
 use clap::Parser;
-use num::BigInt;
 use crate::cli::Cli;
-use crate::math::xgcd;
+use crate::rsa::KeySet;
+
+fn main() {
+    let cli = Cli::parse();
+    env_logger::Builder::new()
+        .filter_level(cli.verbose.into())
+        .init();
+    // Errors are printed with Display, the Debug form of a validation report is unreadable
+    if let Err(error) = run(cli) {
+        eprintln!("Error: {}", error);
+        process::exit(1);
+    }
+}
 
-fn main() -> Result<(), Box<dyn Error>> {
-    //let cli = Cli::parse();
-    //env_logger::Builder::new()
-        //.filter_level(cli.verbose.into())
-        //.init();
-    //cli.command.execute()
-    dbg!(xgcd(&BigInt::from(179usize), &BigInt::from(124usize)));
-    Ok(())
+fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    if let Some(key_dir) = cli.key_dir {
+        KeySet::set_key_root(key_dir)?;
+    }
+    cli.command.execute()
 }
@@ -1,11 +1,14 @@
 mod constants;
+mod provable;
 
 use log::trace;
 use num::{BigInt, BigUint, Integer, Signed, ToPrimitive};
 use num::bigint::{RandBigInt, ToBigInt};
-use rand::prelude::ThreadRng;
+use rand::{CryptoRng, Rng, RngCore};
 use crate::math::constants::{MILLER_RABIN_ROUNDS, SMALL_PRIMES};
 
+pub use crate::math::provable::{new_provable_prime, PrimeCertificate};
+
 pub fn modular_pow(base: &BigUint, exponent: &BigUint, modulus: &BigUint) -> BigUint {
     let mut exponent = exponent.clone();
     if modulus == &BigUint::from(1u8) {
@@ -20,7 +23,7 @@ pub fn modular_pow(base: &BigUint, exponent: &BigUint, modulus: &BigUint) -> Big
             if &exponent % two == one {
                 result = result * &base % modulus;
             }
-            exponent = exponent >> 1;
+            exponent >>= 1;
             base = &base * &base % modulus;
         }
         result
@@ -35,7 +38,6 @@ pub fn xgcd(a: &BigInt, b: &BigInt) -> (BigInt, BigInt, BigInt) {
     let mut y0 = BigInt::ZERO;
     let mut y1 = BigInt::from(1u8);
     loop {
-        println!("x0: {}, y0: {}, x1: {}, y1: {}", &x0, &y0, &x1, &y1);
         let q = &a / &b;
         let a_old = a.clone();
         a = b.clone();
@@ -62,11 +64,14 @@ pub fn modular_inverse(e: &BigUint, phi: &BigUint) -> BigUint {
     inverse.to_biguint().unwrap()
 }
 
-pub fn new_prime(bit_length: u64) -> BigUint {
-    let mut rng = rand::thread_rng();
+/// Draws candidates and their Miller-Rabin bases from rng, so a seeded rng always yields the same prime
+pub fn new_prime<R: RngCore + CryptoRng>(rng: &mut R, bit_length: u64) -> BigUint {
+    assert!(bit_length >= 2, "Primes need at least two bits");
+    // Setting the top two bits makes the product of two such primes exactly twice as long
+    let top_bits = BigUint::from(3u8) << (bit_length - 2);
     loop {
-        let candidate: BigUint = rng.gen_biguint(bit_length);
-        if is_prime(&candidate) {
+        let candidate: BigUint = rng.gen_biguint(bit_length) | &top_bits | BigUint::from(1u8);
+        if is_probable_prime(rng, &candidate, MILLER_RABIN_ROUNDS) {
             trace!("Successful Prime: {:?}", candidate);
             return candidate;
         }
@@ -74,25 +79,18 @@ pub fn new_prime(bit_length: u64) -> BigUint {
     }
 }
 
-pub fn is_prime(candidate: &BigUint) -> bool {
-    let mut rng = rand::thread_rng();
-    if candidate == &BigUint::ZERO || candidate == &BigUint::from(2u8) {
-        false
-    }
-    else if candidate.is_even() {
-        false
-    }
-    else if !divide_small_primes(candidate) {
-        false
-    }
-    else if !fermat(&mut rng, candidate) {
-        false
-    }
-    else if !miller_rabin(&mut rng, candidate, MILLER_RABIN_ROUNDS) {
-        false
-    } else {
-        true
-    }
+pub fn is_prime<R: RngCore + CryptoRng>(rng: &mut R, candidate: &BigUint) -> bool {
+    is_probable_prime(rng, candidate, MILLER_RABIN_ROUNDS)
+}
+
+/// Primality test with a caller chosen number of Miller-Rabin rounds
+pub fn is_probable_prime<R: RngCore + CryptoRng>(rng: &mut R, candidate: &BigUint, rounds: usize) -> bool {
+    candidate != &BigUint::ZERO
+        && candidate != &BigUint::from(2u8)
+        && !candidate.is_even()
+        && divide_small_primes(candidate)
+        && fermat(rng, candidate)
+        && miller_rabin(rng, candidate, rounds)
 }
 
 fn divide_small_primes(number: &BigUint) -> bool {
@@ -104,7 +102,7 @@ fn divide_small_primes(number: &BigUint) -> bool {
     true
 }
 
-fn fermat(rng: &mut ThreadRng, candidate: &BigUint) -> bool {
+fn fermat<R: Rng>(rng: &mut R, candidate: &BigUint) -> bool {
     let random = rng.gen_biguint_below(candidate);
     let exponent = candidate - BigUint::from(1u8);
     let result = modular_pow(&random, &exponent, candidate);
@@ -112,13 +110,13 @@ fn fermat(rng: &mut ThreadRng, candidate: &BigUint) -> bool {
 }
 
 // needs to be fixed
-fn miller_rabin(rng: &mut ThreadRng, candidate: &BigUint, limit: usize) -> bool {
-    let (d, s) = rewrite(&candidate);
+fn miller_rabin<R: Rng>(rng: &mut R, candidate: &BigUint, limit: usize) -> bool {
+    let (d, s) = rewrite(candidate);
     let step = (s - &BigUint::from(1u8)).to_usize().unwrap();
 
     for _ in 0..limit {
         let a = rng.gen_biguint_range(&BigUint::from(2u8), &(candidate - &BigUint::from(1u8)));
-        let mut x = modular_pow(&a, &d, &candidate);
+        let mut x = modular_pow(&a, &d, candidate);
         if x == BigUint::from(1u8) || x == (candidate - &BigUint::from(1u8)) {
             continue
         }
@@ -147,10 +145,25 @@ fn rewrite(n: &BigUint) -> (BigUint,BigUint) {
     let mut s: BigUint = BigUint::ZERO;
     let mut d: BigUint = n - &one;
 
-    while d.is_even() == true {
+    while d.is_even() {
         d = d.div_floor(&BigUint::from(2u8));
         s += &one;
     }
 
     (d.clone(), s)
 }
+
+pub fn i2osp(number: &BigUint, length: usize) -> Option<Vec<u8>> {
+    let bytes = number.to_bytes_be();
+    if number == &BigUint::ZERO {
+        Some(vec![0u8; length])
+    }
+    else if bytes.len() > length {
+        None
+    }
+    else {
+        let mut padded = vec![0u8; length - bytes.len()];
+        padded.extend_from_slice(&bytes);
+        Some(padded)
+    }
+}
@@ -0,0 +1,175 @@
+use std::error::Error;
+use std::fmt;
+use num::{BigUint, Integer, One, ToPrimitive};
+use sha2::{Digest, Sha256};
+use crate::math::{i2osp, modular_pow};
+
+const HASH_BITS: u64 = 256;
+// Candidates shorter than this are proven prime by trial division alone
+const TRIAL_DIVISION_BITS: u64 = 33;
+
+/// One link of a Pocklington chain, `prime - 1` is a multiple of the previous prime in the chain
+#[derive(Debug, PartialEq, Clone)]
+pub struct PocklingtonStep {
+    pub prime: BigUint,
+    pub witness: BigUint
+}
+
+/// Proof of primality produced by `new_provable_prime`, checkable without trusting the generator
+#[derive(Debug, PartialEq, Clone)]
+pub struct PrimeCertificate {
+    pub small_prime: u64,
+    pub steps: Vec<PocklingtonStep>
+}
+
+impl PrimeCertificate {
+    pub fn prime(&self) -> BigUint {
+        match self.steps.last() {
+            Some(step) => step.prime.clone(),
+            None => BigUint::from(self.small_prime)
+        }
+    }
+
+    pub fn verify(&self) -> bool {
+        if self.small_prime >= 1 << TRIAL_DIVISION_BITS || !is_small_prime(self.small_prime) {
+            return false;
+        }
+        let mut factor = BigUint::from(self.small_prime);
+        for step in &self.steps {
+            let prime = &step.prime;
+            let two_factor = &factor * 2u8;
+            if prime <= &two_factor || !(prime - 1u8).is_multiple_of(&two_factor) {
+                return false;
+            }
+            // Pocklington needs the known prime factor of prime - 1 to exceed sqrt(prime) - 1
+            if (&factor + 1u8).pow(2) <= *prime {
+                return false;
+            }
+            if step.witness < BigUint::from(2u8) || step.witness >= prime - 1u8 {
+                return false;
+            }
+            let z = modular_pow(&step.witness, &((prime - 1u8) / &factor), prime);
+            if z == BigUint::ZERO || !(&z - 1u8).gcd(prime).is_one() || !modular_pow(&z, &factor, prime).is_one() {
+                return false;
+            }
+            factor = prime.clone();
+        }
+        true
+    }
+}
+
+impl fmt::Display for PrimeCertificate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:x} is prime by trial division", self.small_prime)?;
+        for step in &self.steps {
+            writeln!(f, "{:x} is prime by Pocklington with witness {:x}", step.prime, step.witness)?;
+        }
+        Ok(())
+    }
+}
+
+/// Shawe-Taylor random prime construction from FIPS 186-5 Appendix B.10 with SHA-256,
+/// returns the certificate and the seed to continue from for further primes
+pub fn new_provable_prime(bit_length: u64, seed: &[u8]) -> Result<(PrimeCertificate, Vec<u8>), Box<dyn Error>> {
+    if bit_length < 2 {
+        return Err("Provable primes need at least two bits".into());
+    }
+    if seed.is_empty() {
+        return Err("Provable prime generation needs a seed".into());
+    }
+    let mut seed = Seed {
+        value: BigUint::from_bytes_be(seed),
+        length: seed.len()
+    };
+    let certificate = shawe_taylor(bit_length, &mut seed)?;
+    Ok((certificate, seed.to_bytes()))
+}
+
+fn shawe_taylor(length: u64, seed: &mut Seed) -> Result<PrimeCertificate, Box<dyn Error>> {
+    let top = BigUint::one() << (length - 1);
+    if length < TRIAL_DIVISION_BITS {
+        for _ in 0..=4 * length {
+            let c = seed.hash(0) ^ seed.hash(1);
+            seed.advance(2);
+            let c = ((&top + c % &top) | BigUint::one()).to_u64().unwrap();
+            if is_small_prime(c) {
+                return Ok(PrimeCertificate {
+                    small_prime: c,
+                    steps: Vec::new()
+                });
+            }
+        }
+        return Err("Shawe-Taylor failed to find a small prime".into());
+    }
+
+    let mut certificate = shawe_taylor(length.div_ceil(2) + 1, seed)?;
+    let c0 = certificate.prime();
+    let two_c0 = &c0 * 2u8;
+    let iterations = length.div_ceil(HASH_BITS) - 1;
+    let x = seed.hash_span(iterations);
+    let x = &top + x % &top;
+    let mut t = x.div_ceil(&two_c0);
+    for _ in 0..4 * length {
+        if &two_c0 * &t + 1u8 > BigUint::one() << length {
+            t = top.div_ceil(&two_c0);
+        }
+        let c = &two_c0 * &t + 1u8;
+        let a = BigUint::from(2u8) + seed.hash_span(iterations) % (&c - 3u8);
+        let z = modular_pow(&a, &(&t * 2u8), &c);
+        if z != BigUint::ZERO && (&z - 1u8).gcd(&c).is_one() && modular_pow(&z, &c0, &c).is_one() {
+            certificate.steps.push(PocklingtonStep {
+                prime: c,
+                witness: a
+            });
+            return Ok(certificate);
+        }
+        t += 1u8;
+    }
+    Err("Shawe-Taylor failed to find a prime".into())
+}
+
+fn is_small_prime(candidate: u64) -> bool {
+    if candidate < 4 {
+        return candidate >= 2;
+    }
+    if candidate.is_even() {
+        return false;
+    }
+    (3..)
+        .step_by(2)
+        .take_while(|i| i * i <= candidate)
+        .all(|i| !candidate.is_multiple_of(i))
+}
+
+// The seed is an integer of fixed byte length that wraps around when incremented
+struct Seed {
+    value: BigUint,
+    length: usize
+}
+
+impl Seed {
+    fn hash(&self, offset: u64) -> BigUint {
+        let modulus = BigUint::one() << (8 * self.length);
+        let value = (&self.value + offset) % modulus;
+        let bytes = i2osp(&value, self.length).unwrap();
+        BigUint::from_bytes_be(&Sha256::digest(bytes))
+    }
+
+    fn advance(&mut self, count: u64) {
+        self.value = (&self.value + count) % (BigUint::one() << (8 * self.length));
+    }
+
+    // Concatenated hashes of the next iterations + 1 seed values
+    fn hash_span(&mut self, iterations: u64) -> BigUint {
+        let mut value = BigUint::ZERO;
+        for i in 0..=iterations {
+            value += self.hash(i) << (i * HASH_BITS);
+        }
+        self.advance(iterations + 1);
+        value
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        i2osp(&self.value, self.length).unwrap()
+    }
+}
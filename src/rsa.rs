@@ -1,30 +1,67 @@
+mod crt;
+mod der;
+mod encoding;
+mod fips;
+mod hybrid;
+mod jwk;
+mod keygen;
+mod openssh;
+mod padding;
+mod pbes2;
+mod pem;
+mod protection;
+mod signature;
+mod store;
+mod validation;
+
+use std::collections::HashMap;
+use std::env;
 use std::error::Error;
 use std::fs;
-use std::fs::File;
-use std::future::join;
-use std::io::{BufRead, Read, Write};
-use crate::math::{modular_inverse, modular_pow, new_prime};
-use std::ops::{BitAnd, Sub};
-use std::path::PathBuf;
-use log::{debug, error, info, trace};
-use num::bigint::RandBigInt;
-use num::{BigUint, ToPrimitive};
-use rand::prelude::ThreadRng;
+use crate::math::{modular_pow, PrimeCertificate};
+use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+use std::sync::OnceLock;
+use log::{error, info, trace};
+use num::BigUint;
+use rand::{CryptoRng, RngCore};
+use rayon::prelude::*;
+
+pub use crate::rsa::crt::CrtParameters;
+pub use crate::rsa::encoding::KeyFormat;
+#[cfg(test)]
+pub use crate::rsa::fips::FipsCheck;
+pub use crate::rsa::jwk::jwk_set;
+pub use crate::rsa::keygen::{KeyGenOptions, DEFAULT_PUBLIC_EXPONENT};
+pub use crate::rsa::padding::Padding;
+pub use crate::rsa::signature::{HashAlgorithm, SignatureScheme};
+pub use crate::rsa::store::write_key_file;
+use crate::rsa::store::prepare_key_root;
+pub use crate::rsa::validation::ValidationCheck;
+use crate::rsa::validation::ValidationReport;
+
+pub const DEFAULT_SALT_BITS: u32 = 6;
+const KEY_DIR_VARIABLE: &str = "AMH_RSA_KEY_DIR";
+
+static KEY_ROOT: OnceLock<PathBuf> = OnceLock::new();
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Key {
     exponent: BigUint,
     modulus: BigUint,
-    salt_bits: u32
+    salt_bits: u32,
+    public_exponent: Option<BigUint>,
+    crt: Option<CrtParameters>
 }
 
 #[derive(Debug)]
 pub struct KeySet {
     private_key: Key,
     public_key: Key,
+    #[allow(dead_code)]
     phi: BigUint,
     primes: (BigUint, BigUint),
-    bit_length: u64
+    bit_length: u64,
+    certificates: Option<(PrimeCertificate, PrimeCertificate)>
 }
 
 impl Key {
@@ -32,108 +69,457 @@ impl Key {
         Key {
             exponent,
             modulus,
-            salt_bits
+            salt_bits,
+            public_exponent: None,
+            crt: None
+        }
+    }
+
+    pub fn with_public_exponent(mut self, public_exponent: BigUint) -> Key {
+        self.public_exponent = Some(public_exponent);
+        self
+    }
+
+    pub fn with_crt(mut self, crt: CrtParameters) -> Key {
+        self.crt = Some(crt);
+        self
+    }
+
+    pub fn public_key(&self) -> Option<Key> {
+        self.public_exponent
+            .as_ref()
+            .map(|i| {
+                Key::new(i.clone(), self.modulus.clone(), self.salt_bits)
+            })
+    }
+
+    #[cfg(test)]
+    pub fn modulus(&self) -> &BigUint {
+        &self.modulus
+    }
+
+    pub fn modulus_bits(&self) -> u64 {
+        self.modulus.bits()
+    }
+
+    pub fn salt_bits(&self) -> u32 {
+        self.salt_bits
+    }
+
+    /// The public exponent for both halves of a key pair, unknown for a private key without one.
+    /// A legacy three line private key looks exactly like a public key, so for such a key this is
+    /// its private exponent. Only keys loaded as public keys should be passed to the public exporters.
+    pub fn public_exponent(&self) -> Option<&BigUint> {
+        if self.is_private() {
+            self.public_exponent.as_ref()
+        }
+        else {
+            Some(&self.exponent)
         }
     }
 
-    pub fn encrypt(&self, rng: &mut ThreadRng, input: u8) -> BigUint {
-        trace!("Running enrypt: {}", input);
-        let salt = rng.gen_biguint(self.salt_bits as u64);
-        trace!("Salt: {:b}", &salt);
-        let input = BigUint::from(input);
-        trace!("Input: {:b}", &input);
-        let input = (input << self.salt_bits) | salt;
-        trace!("Salted value: {:b}", &input);
-        modular_pow(&input, &self.exponent, &self.modulus)
+    /// Checks that a private key inverts its public exponent, with and without the CRT values.
+    /// Without a public exponent there is nothing to check against and the answer is unknown.
+    pub fn is_consistent(&self) -> Option<bool> {
+        let public_exponent = self.public_exponent.as_ref()?;
+        if self.crt.as_ref().is_some_and(|i| &i.p * &i.q != self.modulus) {
+            return Some(false);
+        }
+        let message = BigUint::from(2u8);
+        if message >= self.modulus {
+            return Some(false);
+        }
+        let ciphertext = modular_pow(&message, public_exponent, &self.modulus);
+        let plain = modular_pow(&ciphertext, &self.exponent, &self.modulus);
+        Some(plain == message && self.apply(&ciphertext).ok() == Some(message))
     }
 
-    pub fn decrypt(&self, input: &BigUint) -> Result<u8, Box<dyn Error>> {
+    pub fn modulus_length(&self) -> usize {
+        self.modulus.bits().div_ceil(8) as usize
+    }
+
+    pub fn apply(&self, input: &BigUint) -> Result<BigUint, Box<dyn Error>> {
+        if input >= &self.modulus {
+            return Err("Input is out of range for the modulus".into());
+        }
+        match &self.crt {
+            Some(crt) => Ok(crt.apply(input)),
+            None => Ok(modular_pow(input, &self.exponent, &self.modulus))
+        }
+    }
+
+    pub fn encrypt<R: RngCore + CryptoRng>(&self, rng: &mut R, input: &[u8], padding: &Padding) -> Result<Vec<BigUint>, Box<dyn Error>> {
+        let block_size = padding.max_message_length(self)?;
+        input
+            .chunks(block_size)
+            .map(|i| {
+                self.encrypt_block(rng, i, padding)
+            })
+            .collect()
+    }
+
+    pub fn decrypt(&self, input: &[BigUint], padding: &Padding) -> Result<Vec<u8>, Box<dyn Error>> {
+        let plaintext = input
+            .par_iter()
+            .map(|i| {
+                self.decrypt_block(i, padding).map_err(|e| e.to_string())
+            })
+            .collect::<Result<Vec<Vec<u8>>, String>>()?;
+        Ok(plaintext.concat())
+    }
+
+    pub fn encrypt_block<R: RngCore + CryptoRng>(&self, rng: &mut R, input: &[u8], padding: &Padding) -> Result<BigUint, Box<dyn Error>> {
+        trace!("Running enrypt: {:?}", input);
+        let encoded = padding.encode(rng, self, input)?;
+        trace!("Padded value: {:b}", &encoded);
+        self.apply(&encoded)
+    }
+
+    pub fn decrypt_block(&self, input: &BigUint, padding: &Padding) -> Result<Vec<u8>, Box<dyn Error>> {
         trace!("Running decrypt");
-        let decrypted = modular_pow(input, &self.exponent, &self.modulus);
-        trace!("Salted decrypted: {}", &decrypted);
-        let desalted = (&decrypted >> self.salt_bits).to_u8();
-        match desalted {
+        let decoded = self
+            .apply(input)
+            .ok()
+            .and_then(|i| {
+                padding.decode(self, &i)
+            });
+        match decoded {
+            Some(decoded) => {
+                Ok(decoded)
+            }
             None => {
-                error!("Could not decrypt.\nInput: {}\nDecrypted: {:b}", &input, &decrypted);
+                error!("Could not decrypt block {}", &input);
                 Err("Decryption failed".into())
             }
-            Some(desalted) => {
-                Ok(desalted)
-            }
         }
     }
 
-    pub fn save_to_file(&self, mut file: File) -> Result<(), Box<dyn Error>> {
-        let data = format!("{}\n{}\n{}", self.exponent, self.modulus, self.salt_bits);
-        Ok(file.write_all(data.as_bytes())?)
+    pub fn serialize<R: RngCore + CryptoRng>(&self, rng: &mut R, format: KeyFormat) -> Result<Vec<u8>, Box<dyn Error>> {
+        if self.is_private() {
+            self.export_private(rng, format)
+        }
+        else {
+            self.export_public(format)
+        }
+    }
+
+    pub(crate) fn to_native(&self) -> String {
+        if self.public_exponent.is_some() || self.crt.is_some() {
+            self.private_key_fields()
+                .iter()
+                .map(|(label, value)| {
+                    format!("{}: {}", label, value)
+                })
+                .collect::<Vec<String>>()
+                .join("\n")
+        }
+        else {
+            format!("{}\n{}\n{}", self.exponent, self.modulus, self.salt_bits)
+        }
+    }
+
+    // The RFC 8017 RSAPrivateKey fields followed by the salt bits
+    fn private_key_fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = vec![
+            ("version", "0".to_string()),
+            ("modulus", self.modulus.to_string())
+        ];
+        if let Some(public_exponent) = &self.public_exponent {
+            fields.push(("publicExponent", public_exponent.to_string()));
+        }
+        fields.push(("privateExponent", self.exponent.to_string()));
+        if let Some(crt) = &self.crt {
+            fields.push(("prime1", crt.p.to_string()));
+            fields.push(("prime2", crt.q.to_string()));
+            fields.push(("exponent1", crt.dp.to_string()));
+            fields.push(("exponent2", crt.dq.to_string()));
+            fields.push(("coefficient", crt.qinv.to_string()));
+        }
+        fields.push(("saltBits", self.salt_bits.to_string()));
+        fields
+    }
+
+    pub fn save_public_key(&self, name: &str, force: bool) -> Result<(), Box<dyn Error>> {
+        write_key_file(&KeySet::store_path(name, true)?, &self.export_public(KeyFormat::Native)?, false, force)
     }
 
     pub fn load_public_key(name: &str) -> Result<Self, Box<dyn Error>> {
-        let name = format!("{}.pub", name);
-        Self::load_key(&name)
+        if Self::key_path(name, true)?.exists() {
+            return Self::parse(&Self::read_key_file(name, true)?);
+        }
+        // A full private key carries everything needed for the public half
+        let data = Self::read_key_file(name, false)?;
+        let key = Self::parse(&data)?;
+        match key.public_key() {
+            Some(public_key) => Ok(public_key),
+            // A bare native file could just as well be a legacy private key, only its name tells them apart
+            None if Self::is_path(name) && Self::is_native(&data) && !name.ends_with(".pub") => {
+                Err(format!("{} may be a legacy private key, native public key files must end in .pub", name).into())
+            }
+            None if Self::is_path(name) => Ok(key),
+            None => Err(format!("No public key found for {}", name).into())
+        }
+    }
+
+    /// The rng drives the Miller-Rabin rounds of the validation
+    pub fn load_private_key<R: RngCore + CryptoRng>(rng: &mut R, name: &str) -> Result<Self, Box<dyn Error>> {
+       Self::validated(rng, Self::load_key(name)?)
+    }
+
+    pub fn load_private_key_with_passphrase<R: RngCore + CryptoRng>(rng: &mut R, name: &str, passphrase: Option<&str>) -> Result<Self, Box<dyn Error>> {
+        Self::validated(rng, Self::load_unchecked_private_key(name, passphrase)?)
+    }
+
+    /// Loads a private key without validating it, so that a broken key can still be inspected
+    pub fn load_unchecked_private_key(name: &str, passphrase: Option<&str>) -> Result<Self, Box<dyn Error>> {
+        let data = Self::read_key_file(name, false)?;
+        match passphrase {
+            Some(passphrase) if Self::is_protected(&data) => Self::unprotect(&data, passphrase),
+            _ => Self::parse(&data)
+        }
     }
 
-    pub fn load_private_key(name: &str) -> Result<Self, Box<dyn Error>> {
-       Self::load_key(name)
+    // Keys that carry their primes are checked in full before use, legacy keys cannot be
+    fn validated<R: RngCore + CryptoRng>(rng: &mut R, key: Self) -> Result<Self, Box<dyn Error>> {
+        if key.public_exponent.is_some() && key.crt.is_some() {
+            KeySet::from_private_key(key.clone())?.validate(rng)?;
+        }
+        Ok(key)
+    }
+
+    pub fn is_private_key_protected(name: &str) -> Result<bool, Box<dyn Error>> {
+        Ok(Self::is_protected(&Self::read_key_file(name, false)?))
+    }
+
+    pub fn save_private_key<R: RngCore + CryptoRng>(&self, rng: &mut R, name: &str, format: KeyFormat, passphrase: Option<&str>, force: bool) -> Result<(), Box<dyn Error>> {
+        let data = match passphrase {
+            // PKCS#8 and OpenSSH have their own standard encryption which other tools understand
+            Some(passphrase) if format == KeyFormat::Pkcs8Pem => {
+                self.to_encrypted_pkcs8_pem(rng, passphrase)?.into_bytes()
+            }
+            Some(passphrase) if format == KeyFormat::Pkcs8Der => {
+                self.to_encrypted_pkcs8_der(rng, passphrase)?
+            }
+            Some(passphrase) if format == KeyFormat::Openssh => {
+                self.to_openssh_private(rng, Some(passphrase))?.into_bytes()
+            }
+            Some(passphrase) => {
+                self.protect(rng, passphrase, format)?.into_bytes()
+            }
+            None => {
+                self.serialize(rng, format)?
+            }
+        };
+        write_key_file(&KeySet::store_path(name, false)?, &data, true, force)
     }
 
     fn load_key(name: &str) -> Result<Self, Box<dyn Error>> {
+        Self::parse(&Self::read_key_file(name, false)?)
+    }
+
+    fn read_key_file(name: &str, public: bool) -> Result<Vec<u8>, Box<dyn Error>> {
         info!("Loading Key: {}", name);
-        let key_root = KeySet::get_key_root()?;
-        let file_name = key_root.join(name);
+        let file_name = Self::key_path(name, public)?;
         trace!("Key File: {:?}", file_name);
-        let lines: Vec<String> = fs::read_to_string(file_name)?
+        Ok(fs::read(file_name)?)
+    }
+
+    // Names containing a path separator refer to a file outside the key store
+    fn is_path(name: &str) -> bool {
+        name.contains('/') || name.contains(MAIN_SEPARATOR)
+    }
+
+    // Saving only ever goes to the key store, so only loading accepts a file path
+    fn key_path(name: &str, public: bool) -> Result<PathBuf, Box<dyn Error>> {
+        if !Self::is_path(name) {
+            return KeySet::store_path(name, public);
+        }
+        if public {
+            Ok(PathBuf::from(format!("{}.pub", name)))
+        }
+        else {
+            Ok(Path::new(name).to_path_buf())
+        }
+    }
+
+    fn is_native(data: &[u8]) -> bool {
+        !(pem::is_pem(data) || data.first() == Some(&der::TAG_SEQUENCE) || data.starts_with(openssh::SSH_RSA.as_bytes())
+            || data.trim_ascii_start().starts_with(b"{"))
+    }
+
+    fn parse(data: &[u8]) -> Result<Self, Box<dyn Error>> {
+        if Self::is_protected(data) {
+            return Err("Private key is passphrase protected".into());
+        }
+        if !Self::is_native(data) {
+            return Self::import(data, DEFAULT_SALT_BITS);
+        }
+        let lines: Vec<String> = std::str::from_utf8(data)?
             .lines()
             .map(String::from)
             .collect();
-        assert_eq!(lines.len(), 3);
+        if lines.first().is_some_and(|i| i.contains(':')) {
+            return Self::parse_labeled(&lines);
+        }
+        if lines.len() != 3 && lines.len() != 8 {
+            return Err(format!("Malformed key file, expected 3 or 8 lines but found {}", lines.len()).into());
+        }
         let exponent = lines[0].parse::<BigUint>()?;
         let modulus = lines[1].parse::<BigUint>()?;
         let salt_bits = lines[2].parse::<u32>()?;
+        let crt = if lines.len() == 8 {
+            Some(CrtParameters {
+                p: lines[3].parse::<BigUint>()?,
+                q: lines[4].parse::<BigUint>()?,
+                dp: lines[5].parse::<BigUint>()?,
+                dq: lines[6].parse::<BigUint>()?,
+                qinv: lines[7].parse::<BigUint>()?
+            })
+        }
+        else {
+            None
+        };
         Ok(Self {
             exponent,
             modulus,
-            salt_bits
+            salt_bits,
+            public_exponent: None,
+            crt
+        })
+    }
+
+    fn parse_labeled(lines: &[String]) -> Result<Self, Box<dyn Error>> {
+        let mut fields = HashMap::new();
+        for i in lines {
+            let (label, value) = i
+                .split_once(':')
+                .ok_or_else(|| format!("Malformed key file line: {}", i))?;
+            fields.insert(label.trim(), value.trim());
+        }
+        let field = |label: &str| -> Result<BigUint, Box<dyn Error>> {
+            let value = fields
+                .get(label)
+                .ok_or_else(|| format!("Key file is missing the {} field", label))?;
+            Ok(value.parse::<BigUint>()?)
+        };
+        if fields.get("version").is_some_and(|i| *i != "0") {
+            return Err("Unsupported private key version".into());
+        }
+        let salt_bits = fields
+            .get("saltBits")
+            .ok_or("Key file is missing the saltBits field")?
+            .parse::<u32>()?;
+        let crt = if fields.contains_key("prime1") {
+            Some(CrtParameters {
+                p: field("prime1")?,
+                q: field("prime2")?,
+                dp: field("exponent1")?,
+                dq: field("exponent2")?,
+                qinv: field("coefficient")?
+            })
+        }
+        else {
+            None
+        };
+        let public_exponent = if fields.contains_key("publicExponent") {
+            Some(field("publicExponent")?)
+        }
+        else {
+            None
+        };
+        Ok(Self {
+            exponent: field("privateExponent")?,
+            modulus: field("modulus")?,
+            salt_bits,
+            public_exponent,
+            crt
         })
     }
 }
 
 impl KeySet {
-    pub fn new(salt_bits: u32, bit_length: u64) -> Self {
-        let prime_bit_length = bit_length / 2;
-        let p = new_prime(prime_bit_length);
-        let q = new_prime(prime_bit_length);
-        let n = &p * &q;
-        let phi = p.clone().sub(1u8) * q.clone().sub(1u8);
-        let e = BigUint::from(2usize.pow(16) + 1);
-        let d = modular_inverse(&e, &phi);
-        KeySet {
-            private_key: Key::new(d, n.clone(), salt_bits),
-            public_key: Key::new(e, n.clone(), salt_bits),
+    #[cfg(test)]
+    pub fn new<R: RngCore + CryptoRng>(rng: &mut R, salt_bits: u32, bit_length: u64) -> Self {
+        Self::generate(rng, &KeyGenOptions::new(salt_bits, bit_length))
+            .expect("The default public exponent is always valid")
+    }
+
+    pub fn from_private_key(private_key: Key) -> Result<Self, Box<dyn Error>> {
+        let public_key = private_key
+            .public_key()
+            .ok_or("Private key does not carry its public exponent")?;
+        let (p, q) = match &private_key.crt {
+            Some(crt) => (crt.p.clone(), crt.q.clone()),
+            None => return Err("Private key does not carry its primes".into())
+        };
+        // phi is undefined for primes below two, which validation would report as not prime
+        let failures = [(&p, ValidationCheck::PrimeP), (&q, ValidationCheck::PrimeQ)]
+            .into_iter()
+            .filter(|(prime, _)| prime.bits() <= 1)
+            .map(|(_, check)| check)
+            .collect::<Vec<ValidationCheck>>();
+        if !failures.is_empty() {
+            return Err(Box::new(ValidationReport { failures }));
+        }
+        let phi = (&p - 1u8) * (&q - 1u8);
+        let bit_length = private_key.modulus.bits();
+        Ok(KeySet {
+            private_key,
+            public_key,
             phi,
             primes: (p, q),
-            bit_length: 0
+            bit_length,
+            certificates: None
+        })
+    }
+
+    pub fn save_keys<R: RngCore + CryptoRng>(&self, rng: &mut R, name: &str, force: bool) -> Result<(), Box<dyn Error>> {
+        self.save_keys_as(rng, name, KeyFormat::Native, None, force)
+    }
+
+    /// The rng supplies the salts, nonces and check values of the written private key
+    pub fn save_keys_as<R: RngCore + CryptoRng>(&self, rng: &mut R, name: &str, format: KeyFormat, passphrase: Option<&str>, force: bool) -> Result<(), Box<dyn Error>> {
+        let public_path = Self::store_path(name, true)?;
+        // Checked up front so a refused save does not leave half a key pair behind
+        if !force && (public_path.exists() || Self::store_path(name, false)?.exists()) {
+            return Err(format!("A key named {} already exists", name).into());
         }
+        write_key_file(&public_path, &self.public_key.export_public(format)?, false, force)?;
+        self.private_key.save_private_key(rng, name, format, passphrase, force)
     }
 
-    pub fn save_keys(&self, name: &str) -> Result<(), Box<dyn Error>> {
-        let key_root = Self::get_key_root()?;
-        let public_file = File::create(key_root.join(format!("{}.pub", name)))?;
-        self.public_key.save_to_file(public_file)?;
-        let private_file = File::create(key_root.join(name))?;
-        self.private_key.save_to_file(private_file)?;
+    /// Overrides the key store location for the rest of the process
+    pub fn set_key_root(key_root: PathBuf) -> Result<(), Box<dyn Error>> {
+        if KEY_ROOT.get_or_init(|| key_root.clone()) != &key_root {
+            return Err("The key root has already been set".into());
+        }
         Ok(())
     }
 
+    /// The key store location, from `set_key_root`, then `AMH_RSA_KEY_DIR`, then `~/.amh_rsa`
     pub fn get_key_root() -> Result<PathBuf, Box<dyn Error>> {
-        let home = dirs::home_dir().unwrap();
-        let key_root = home.join(".amh_rsa");
-        if !key_root.exists() {
-            fs::create_dir(&key_root)?;
-        }
+        let key_root = match KEY_ROOT.get() {
+            Some(key_root) => key_root.clone(),
+            None => match env::var_os(KEY_DIR_VARIABLE).filter(|i| !i.is_empty()) {
+                Some(key_dir) => PathBuf::from(key_dir),
+                None => dirs::home_dir()
+                    .ok_or("Could not determine the home directory")?
+                    .join(".amh_rsa")
+            }
+        };
+        prepare_key_root(&key_root)?;
         Ok(key_root)
     }
 
+    pub fn bit_length(&self) -> u64 {
+        self.bit_length
+    }
+
+    /// Primality certificates for p and q when they were generated as provable primes
+    pub fn prime_certificates(&self) -> Option<&(PrimeCertificate, PrimeCertificate)> {
+        self.certificates.as_ref()
+    }
+
     #[cfg(test)]
     pub fn get_public_key(&self) -> Key {
         self.public_key.clone()
@@ -143,4 +529,14 @@ impl KeySet {
     pub fn get_private_key(&self) -> Key {
         self.private_key.clone()
     }
+
+    #[cfg(test)]
+    pub fn get_primes(&self) -> (BigUint, BigUint) {
+        self.primes.clone()
+    }
+
+    #[cfg(test)]
+    pub fn get_private_key_without_crt(&self) -> Key {
+        Key::new(self.private_key.exponent.clone(), self.private_key.modulus.clone(), self.private_key.salt_bits)
+    }
 }
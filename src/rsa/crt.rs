@@ -0,0 +1,35 @@
+use num::BigUint;
+use crate::math::{modular_inverse, modular_pow};
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct CrtParameters {
+    pub(crate) p: BigUint,
+    pub(crate) q: BigUint,
+    pub(crate) dp: BigUint,
+    pub(crate) dq: BigUint,
+    pub(crate) qinv: BigUint
+}
+
+impl CrtParameters {
+    pub fn new(p: BigUint, q: BigUint, private_exponent: &BigUint) -> Self {
+        let dp = private_exponent % (&p - 1u8);
+        let dq = private_exponent % (&q - 1u8);
+        let qinv = modular_inverse(&q, &p);
+        CrtParameters {
+            p,
+            q,
+            dp,
+            dq,
+            qinv
+        }
+    }
+
+    pub fn apply(&self, input: &BigUint) -> BigUint {
+        let m1 = modular_pow(input, &self.dp, &self.p);
+        let m2 = modular_pow(input, &self.dq, &self.q);
+        // Adding p before subtracting keeps the difference non-negative
+        let difference = (&m1 + &self.p - (&m2 % &self.p)) % &self.p;
+        let h = (&self.qinv * difference) % &self.p;
+        m2 + h * &self.q
+    }
+}
@@ -0,0 +1,119 @@
+use std::error::Error;
+use num::BigUint;
+
+pub const TAG_INTEGER: u8 = 0x02;
+pub const TAG_BIT_STRING: u8 = 0x03;
+pub const TAG_OCTET_STRING: u8 = 0x04;
+pub const TAG_NULL: u8 = 0x05;
+pub const TAG_OBJECT_IDENTIFIER: u8 = 0x06;
+pub const TAG_SEQUENCE: u8 = 0x30;
+
+pub fn encode(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![tag];
+    if contents.len() < 0x80 {
+        encoded.push(contents.len() as u8);
+    }
+    else {
+        let length = contents.len().to_be_bytes();
+        let skip = length.iter().take_while(|i| **i == 0).count();
+        encoded.push(0x80 | (length.len() - skip) as u8);
+        encoded.extend_from_slice(&length[skip..]);
+    }
+    encoded.extend_from_slice(contents);
+    encoded
+}
+
+pub fn sequence(items: &[Vec<u8>]) -> Vec<u8> {
+    encode(TAG_SEQUENCE, &items.concat())
+}
+
+pub fn integer(value: &BigUint) -> Vec<u8> {
+    let mut bytes = value.to_bytes_be();
+    // INTEGER is signed so a set top bit needs a leading zero
+    if bytes[0] & 0x80 != 0 {
+        bytes.insert(0, 0);
+    }
+    encode(TAG_INTEGER, &bytes)
+}
+
+pub struct Reader<'a> {
+    data: &'a [u8],
+    position: usize
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Reader {
+            data,
+            position: 0
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.position == self.data.len()
+    }
+
+    pub fn finish(&self) -> Result<(), Box<dyn Error>> {
+        if self.is_empty() {
+            Ok(())
+        }
+        else {
+            Err("Trailing data after DER structure".into())
+        }
+    }
+
+    pub fn peek_tag(&self) -> Option<u8> {
+        self.data.get(self.position).copied()
+    }
+
+    pub fn read(&mut self, tag: u8) -> Result<&'a [u8], Box<dyn Error>> {
+        let found = self.take(1)?[0];
+        if found != tag {
+            return Err(format!("Expected DER tag {:#04x} but found {:#04x}", tag, found).into());
+        }
+        let first = self.take(1)?[0];
+        let length = if first < 0x80 {
+            first as usize
+        }
+        else {
+            let count = (first & 0x7f) as usize;
+            if count == 0 || count > size_of::<usize>() {
+                return Err("Unsupported DER length".into());
+            }
+            let bytes = self.take(count)?;
+            if bytes[0] == 0 {
+                return Err("Non-minimal DER length".into());
+            }
+            let length = bytes.iter().fold(0usize, |acc, i| (acc << 8) | *i as usize);
+            if length < 0x80 {
+                return Err("Non-minimal DER length".into());
+            }
+            length
+        };
+        self.take(length)
+    }
+
+    pub fn read_sequence(&mut self) -> Result<Reader<'a>, Box<dyn Error>> {
+        Ok(Reader::new(self.read(TAG_SEQUENCE)?))
+    }
+
+    pub fn read_integer(&mut self) -> Result<BigUint, Box<dyn Error>> {
+        let bytes = self.read(TAG_INTEGER)?;
+        match bytes {
+            [] => Err("Empty DER integer".into()),
+            [first, ..] if first & 0x80 != 0 => Err("Negative DER integer".into()),
+            [0, second, ..] if second & 0x80 == 0 => Err("Non-minimal DER integer".into()),
+            _ => Ok(BigUint::from_bytes_be(bytes))
+        }
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], Box<dyn Error>> {
+        let end = self.position
+            .checked_add(length)
+            .filter(|i| *i <= self.data.len())
+            .ok_or("DER structure is truncated")?;
+        let slice = &self.data[self.position..end];
+        self.position = end;
+        Ok(slice)
+    }
+}
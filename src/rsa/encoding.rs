@@ -0,0 +1,258 @@
+use std::error::Error;
+use log::trace;
+use rand::{CryptoRng, RngCore};
+use crate::rsa::der::Reader;
+use crate::rsa::{der, pem, CrtParameters, Key, KeySet};
+use crate::rsa::openssh::{OPENSSH_PRIVATE_LABEL, SSH_RSA};
+
+const PKCS1_PUBLIC_LABEL: &str = "RSA PUBLIC KEY";
+const PKCS1_PRIVATE_LABEL: &str = "RSA PRIVATE KEY";
+const SPKI_LABEL: &str = "PUBLIC KEY";
+const PKCS8_LABEL: &str = "PRIVATE KEY";
+
+// 1.2.840.113549.1.1.1
+const RSA_ENCRYPTION_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+
+#[derive(clap::ValueEnum, Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum KeyFormat {
+    /// The plain text format of this tool, the only one that keeps the salt bits
+    #[default]
+    Native,
+    /// PKCS#1 RSAPublicKey or RSAPrivateKey, PEM armored
+    Pkcs1Pem,
+    /// PKCS#1 RSAPublicKey or RSAPrivateKey, raw DER
+    Pkcs1Der,
+    /// SubjectPublicKeyInfo or PKCS#8 PrivateKeyInfo, PEM armored
+    Pkcs8Pem,
+    /// SubjectPublicKeyInfo or PKCS#8 PrivateKeyInfo, raw DER
+    Pkcs8Der,
+    /// An ssh-rsa public key line or an openssh-key-v1 private key
+    Openssh,
+    /// An RFC 7517 JSON Web Key
+    Jwk
+}
+
+impl Key {
+    /// Uses the public half of a private key
+    pub fn to_pkcs1_public_der(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let public_exponent = self.public_exponent()
+            .ok_or("Private key does not carry its public exponent")?;
+        Ok(der::sequence(&[
+            der::integer(&self.modulus),
+            der::integer(public_exponent)
+        ]))
+    }
+
+    pub fn to_pkcs1_private_der(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let public_exponent = self.public_exponent
+            .as_ref()
+            .ok_or("Private key does not carry its public exponent")?;
+        let crt = self.crt
+            .as_ref()
+            .ok_or("Private key does not carry its primes")?;
+        Ok(der::sequence(&[
+            der::integer(&0u8.into()),
+            der::integer(&self.modulus),
+            der::integer(public_exponent),
+            der::integer(&self.exponent),
+            der::integer(&crt.p),
+            der::integer(&crt.q),
+            der::integer(&crt.dp),
+            der::integer(&crt.dq),
+            der::integer(&crt.qinv)
+        ]))
+    }
+
+    pub fn from_pkcs1_public_der(data: &[u8], salt_bits: u32) -> Result<Self, Box<dyn Error>> {
+        let mut outer = Reader::new(data);
+        let mut sequence = outer.read_sequence()?;
+        outer.finish()?;
+        let modulus = sequence.read_integer()?;
+        let exponent = sequence.read_integer()?;
+        sequence.finish()?;
+        Ok(Key::new(exponent, modulus, salt_bits))
+    }
+
+    pub fn from_pkcs1_private_der(data: &[u8], salt_bits: u32) -> Result<Self, Box<dyn Error>> {
+        let mut outer = Reader::new(data);
+        let mut sequence = outer.read_sequence()?;
+        outer.finish()?;
+        if sequence.read_integer()? != 0u8.into() {
+            return Err("Only two-prime RSAPrivateKey structures are supported".into());
+        }
+        let modulus = sequence.read_integer()?;
+        let public_exponent = sequence.read_integer()?;
+        let exponent = sequence.read_integer()?;
+        let crt = CrtParameters {
+            p: sequence.read_integer()?,
+            q: sequence.read_integer()?,
+            dp: sequence.read_integer()?,
+            dq: sequence.read_integer()?,
+            qinv: sequence.read_integer()?
+        };
+        sequence.finish()?;
+        Ok(Key::new(exponent, modulus, salt_bits)
+            .with_public_exponent(public_exponent)
+            .with_crt(crt))
+    }
+
+    pub fn to_spki_der(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut bits = vec![0u8];
+        bits.extend_from_slice(&self.to_pkcs1_public_der()?);
+        Ok(der::sequence(&[
+            rsa_algorithm_identifier(),
+            der::encode(der::TAG_BIT_STRING, &bits)
+        ]))
+    }
+
+    pub fn to_pkcs8_der(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(der::sequence(&[
+            der::integer(&0u8.into()),
+            rsa_algorithm_identifier(),
+            der::encode(der::TAG_OCTET_STRING, &self.to_pkcs1_private_der()?)
+        ]))
+    }
+
+    pub fn from_spki_der(data: &[u8], salt_bits: u32) -> Result<Self, Box<dyn Error>> {
+        let mut outer = Reader::new(data);
+        let mut sequence = outer.read_sequence()?;
+        outer.finish()?;
+        read_rsa_algorithm_identifier(&mut sequence)?;
+        let bits = sequence.read(der::TAG_BIT_STRING)?;
+        sequence.finish()?;
+        match bits.split_first() {
+            Some((0, public_key)) => Self::from_pkcs1_public_der(public_key, salt_bits),
+            _ => Err("Malformed SubjectPublicKeyInfo bit string".into())
+        }
+    }
+
+    pub fn from_pkcs8_der(data: &[u8], salt_bits: u32) -> Result<Self, Box<dyn Error>> {
+        let mut outer = Reader::new(data);
+        let mut sequence = outer.read_sequence()?;
+        outer.finish()?;
+        // Version 1 is OneAsymmetricKey from RFC 5958 whose extra fields are ignored
+        if sequence.read_integer()? > 1u8.into() {
+            return Err("Unsupported PrivateKeyInfo version".into());
+        }
+        read_rsa_algorithm_identifier(&mut sequence)?;
+        let private_key = sequence.read(der::TAG_OCTET_STRING)?;
+        Self::from_pkcs1_private_der(private_key, salt_bits)
+    }
+
+    pub fn to_pkcs1_public_pem(&self) -> Result<String, Box<dyn Error>> {
+        Ok(pem::encode(PKCS1_PUBLIC_LABEL, &self.to_pkcs1_public_der()?))
+    }
+
+    pub fn to_pkcs1_private_pem(&self) -> Result<String, Box<dyn Error>> {
+        Ok(pem::encode(PKCS1_PRIVATE_LABEL, &self.to_pkcs1_private_der()?))
+    }
+
+    /// Exports the public half of a private key, never its private exponent
+    pub fn export_public(&self, format: KeyFormat) -> Result<Vec<u8>, Box<dyn Error>> {
+        match format {
+            KeyFormat::Native => {
+                let public_key = if self.is_private() {
+                    self.public_key().ok_or("Private key does not carry its public exponent")?
+                }
+                else {
+                    self.clone()
+                };
+                Ok(public_key.to_native().into_bytes())
+            }
+            KeyFormat::Pkcs1Pem => Ok(self.to_pkcs1_public_pem()?.into_bytes()),
+            KeyFormat::Pkcs1Der => self.to_pkcs1_public_der(),
+            KeyFormat::Pkcs8Pem => Ok(pem::encode(SPKI_LABEL, &self.to_spki_der()?).into_bytes()),
+            KeyFormat::Pkcs8Der => self.to_spki_der(),
+            KeyFormat::Openssh => Ok(self.to_openssh_public()?.into_bytes()),
+            KeyFormat::Jwk => Ok(format!("{:#}\n", self.to_public_jwk()?).into_bytes())
+        }
+    }
+
+    /// The rng is only drawn from for the OpenSSH check values
+    pub fn export_private<R: RngCore + CryptoRng>(&self, rng: &mut R, format: KeyFormat) -> Result<Vec<u8>, Box<dyn Error>> {
+        match format {
+            KeyFormat::Native => Ok(self.to_native().into_bytes()),
+            KeyFormat::Pkcs1Pem => Ok(self.to_pkcs1_private_pem()?.into_bytes()),
+            KeyFormat::Pkcs1Der => self.to_pkcs1_private_der(),
+            KeyFormat::Pkcs8Pem => Ok(pem::encode(PKCS8_LABEL, &self.to_pkcs8_der()?).into_bytes()),
+            KeyFormat::Pkcs8Der => self.to_pkcs8_der(),
+            KeyFormat::Openssh => Ok(self.to_openssh_private(rng, None)?.into_bytes()),
+            KeyFormat::Jwk => Ok(format!("{:#}\n", self.to_private_jwk()?).into_bytes())
+        }
+    }
+
+    /// Reads a PKCS#1, PKCS#8, SubjectPublicKeyInfo, OpenSSH or JWK key
+    pub fn import(data: &[u8], salt_bits: u32) -> Result<Self, Box<dyn Error>> {
+        if data.trim_ascii_start().starts_with(b"{") {
+            let mut key = Self::from_jwk_json(data)?;
+            key.salt_bits = salt_bits;
+            Ok(key)
+        }
+        else if data.starts_with(SSH_RSA.as_bytes()) {
+            let mut key = Self::from_openssh_public(std::str::from_utf8(data)?)?;
+            key.salt_bits = salt_bits;
+            Ok(key)
+        }
+        else if pem::is_pem(data) {
+            let (label, der) = pem::decode(std::str::from_utf8(data)?)?;
+            trace!("Importing PEM block {}", label);
+            match label.as_str() {
+                PKCS1_PUBLIC_LABEL => Self::from_pkcs1_public_der(&der, salt_bits),
+                PKCS1_PRIVATE_LABEL => Self::from_pkcs1_private_der(&der, salt_bits),
+                SPKI_LABEL => Self::from_spki_der(&der, salt_bits),
+                PKCS8_LABEL => Self::from_pkcs8_der(&der, salt_bits),
+                OPENSSH_PRIVATE_LABEL => {
+                    let mut key = Self::from_openssh_private(&der, None)?;
+                    key.salt_bits = salt_bits;
+                    Ok(key)
+                }
+                _ => Err(format!("Unsupported PEM type {}", label).into())
+            }
+        }
+        else {
+            Self::from_pkcs8_der(data, salt_bits)
+                .or_else(|_| Self::from_pkcs1_private_der(data, salt_bits))
+                .or_else(|_| Self::from_spki_der(data, salt_bits))
+                .or_else(|_| Self::from_pkcs1_public_der(data, salt_bits))
+                .map_err(|_| "Input is not a PKCS#1, PKCS#8 or SubjectPublicKeyInfo key".into())
+        }
+    }
+
+    pub fn is_private(&self) -> bool {
+        self.public_exponent.is_some() || self.crt.is_some()
+    }
+}
+
+fn rsa_algorithm_identifier() -> Vec<u8> {
+    der::sequence(&[
+        der::encode(der::TAG_OBJECT_IDENTIFIER, RSA_ENCRYPTION_OID),
+        der::encode(der::TAG_NULL, &[])
+    ])
+}
+
+fn read_rsa_algorithm_identifier(reader: &mut Reader) -> Result<(), Box<dyn Error>> {
+    let mut algorithm = reader.read_sequence()?;
+    if algorithm.read(der::TAG_OBJECT_IDENTIFIER)? != RSA_ENCRYPTION_OID {
+        return Err("Key algorithm is not rsaEncryption".into());
+    }
+    // The parameters must be NULL but some encoders leave them out entirely
+    if !algorithm.is_empty() && !algorithm.read(der::TAG_NULL)?.is_empty() {
+        return Err("Malformed rsaEncryption parameters".into());
+    }
+    algorithm.finish()
+}
+
+impl KeySet {
+    pub fn export_private<R: RngCore + CryptoRng>(&self, rng: &mut R, format: KeyFormat) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.private_key.export_private(rng, format)
+    }
+
+    pub fn export_encrypted_private<R: RngCore + CryptoRng>(&self, rng: &mut R, format: KeyFormat, passphrase: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        match format {
+            KeyFormat::Pkcs8Pem => Ok(self.private_key.to_encrypted_pkcs8_pem(rng, passphrase)?.into_bytes()),
+            KeyFormat::Pkcs8Der => self.private_key.to_encrypted_pkcs8_der(rng, passphrase),
+            KeyFormat::Openssh => Ok(self.private_key.to_openssh_private(rng, Some(passphrase))?.into_bytes()),
+            _ => Err("Passphrase protected export requires a PKCS#8 or OpenSSH format".into())
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use std::error::Error;
+use std::fmt;
+use log::trace;
+use num::{BigUint, Integer, One};
+use num::bigint::RandBigInt;
+use rand::{CryptoRng, RngCore};
+use crate::math::{is_probable_prime, modular_inverse};
+use crate::rsa::keygen::far_apart;
+use crate::rsa::{KeyGenOptions, KeySet};
+
+/// A requirement of FIPS 186-5 Appendix A.1.3 enforced by `KeySet::generate_fips`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FipsCheck {
+    ModulusSize,
+    PublicExponent,
+    PrimeLowerBound,
+    PrimeCoprime,
+    MillerRabin(usize),
+    PrimeDistance,
+    PrivateExponent
+}
+
+impl fmt::Display for FipsCheck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FipsCheck::ModulusSize => write!(f, "modulus length is 2048, 3072 or 4096 bits"),
+            FipsCheck::PublicExponent => write!(f, "e is odd and in [2^16 + 1, 2^256)"),
+            FipsCheck::PrimeLowerBound => write!(f, "p and q are at least sqrt(2) * 2^(nlen/2 - 1)"),
+            FipsCheck::PrimeCoprime => write!(f, "gcd(p - 1, e) = gcd(q - 1, e) = 1"),
+            FipsCheck::MillerRabin(rounds) => write!(f, "p and q passed {} Miller-Rabin rounds", rounds),
+            FipsCheck::PrimeDistance => write!(f, "|p - q| > 2^(nlen/2 - 100)"),
+            FipsCheck::PrivateExponent => write!(f, "d = e^-1 mod lambda(n) and d > 2^(nlen/2)")
+        }
+    }
+}
+
+impl KeySet {
+    /// Generates a key pair following FIPS 186-5 Appendix A.1.3 and reports the checks applied.
+    /// The standard also requires rng to be an approved SP 800-90A DRBG, which is left to the caller.
+    pub fn generate_fips<R: RngCore + CryptoRng>(rng: &mut R, options: &KeyGenOptions) -> Result<(Self, Vec<FipsCheck>), Box<dyn Error>> {
+        let nlen = options.bit_length;
+        // Table B.1, rounds for an error probability of at most 2^-100 when generating p and q
+        let rounds = match nlen {
+            2048 => 5,
+            3072 | 4096 => 4,
+            _ => return Err("FIPS 186-5 key pairs must be 2048, 3072 or 4096 bits".into())
+        };
+        let e = &options.public_exponent;
+        if e.is_even() || e < &BigUint::from(65537u32) || e.bits() > 256 {
+            return Err("FIPS 186-5 requires an odd public exponent in [2^16 + 1, 2^256)".into());
+        }
+        loop {
+            let p = fips_prime(rng, nlen, e, rounds)?;
+            let q = loop {
+                let q = fips_prime(rng, nlen, e, rounds)?;
+                if far_apart(&p, &q, nlen) {
+                    break q;
+                }
+                trace!("Prime rejected, p and q are too close together");
+            };
+            let lambda = (&p - 1u8).lcm(&(&q - 1u8));
+            let d = modular_inverse(e, &lambda);
+            // A small d is vanishingly unlikely but has to start over with new primes
+            if d > BigUint::one() << (nlen / 2) {
+                let checks = vec![
+                    FipsCheck::ModulusSize,
+                    FipsCheck::PublicExponent,
+                    FipsCheck::PrimeLowerBound,
+                    FipsCheck::PrimeCoprime,
+                    FipsCheck::MillerRabin(rounds),
+                    FipsCheck::PrimeDistance,
+                    FipsCheck::PrivateExponent
+                ];
+                return Ok((Self::from_parts(p, q, e.clone(), d, options.salt_bits), checks));
+            }
+            trace!("Private exponent rejected, d is not larger than 2^(nlen/2)");
+        }
+    }
+}
+
+// Steps 4 and 5 of A.1.3, giving up after 5 * nlen / 2 candidates as the standard requires
+fn fips_prime<R: RngCore + CryptoRng>(rng: &mut R, nlen: u64, e: &BigUint, rounds: usize) -> Result<BigUint, Box<dyn Error>> {
+    // p >= sqrt(2) * 2^(nlen/2 - 1) is the same as p^2 >= 2^(nlen - 1)
+    let lower_bound = BigUint::one() << (nlen - 1);
+    for _ in 0..5 * nlen / 2 {
+        let candidate = rng.gen_biguint(nlen / 2) | BigUint::one();
+        if &candidate * &candidate < lower_bound {
+            continue;
+        }
+        if !(&candidate - 1u8).gcd(e).is_one() {
+            continue;
+        }
+        if is_probable_prime(rng, &candidate, rounds) {
+            return Ok(candidate);
+        }
+    }
+    Err("Failed to find a prime within 5 * nlen / 2 candidates".into())
+}
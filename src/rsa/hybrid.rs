@@ -0,0 +1,95 @@
+use std::error::Error;
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+use aes_gcm::Key as AesKey;
+use log::{error, trace};
+use num::BigUint;
+use rand::{CryptoRng, RngCore};
+use crate::rsa::{Key, Padding};
+
+const MAGIC: &[u8; 6] = b"AMHRSA";
+const VERSION: u8 = 1;
+const CONTENT_KEY_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 12;
+
+impl Key {
+    pub fn encrypt_hybrid<R: RngCore + CryptoRng>(&self, rng: &mut R, input: &[u8], padding: &Padding) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut content_key = [0u8; CONTENT_KEY_LENGTH];
+        rng.fill_bytes(&mut content_key);
+        let mut nonce = [0u8; NONCE_LENGTH];
+        rng.fill_bytes(&mut nonce);
+        let wrapped_key = self.encrypt(rng, &content_key, padding)?;
+        trace!("Wrapped content key into {} blocks", wrapped_key.len());
+
+        let mut header = Vec::new();
+        header.extend_from_slice(MAGIC);
+        header.push(VERSION);
+        header.extend_from_slice(&(wrapped_key.len() as u16).to_be_bytes());
+        for i in wrapped_key.iter() {
+            let block = i.to_bytes_be();
+            header.extend_from_slice(&(block.len() as u16).to_be_bytes());
+            header.extend_from_slice(&block);
+        }
+        header.extend_from_slice(&nonce);
+
+        let cipher = Aes256Gcm::new(AesKey::<Aes256Gcm>::from_slice(&content_key));
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: input, aad: &header })
+            .map_err(|_| "Encryption failed")?;
+        header.extend_from_slice(&ciphertext);
+        Ok(header)
+    }
+
+    pub fn decrypt_hybrid(&self, input: &[u8], padding: &Padding) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut reader = Reader { data: input, position: 0 };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err("Input is not a hybrid ciphertext".into());
+        }
+        let version = reader.take(1)?[0];
+        if version != VERSION {
+            return Err(format!("Unsupported hybrid ciphertext version {}", version).into());
+        }
+        let block_count = reader.take_u16()?;
+        let mut wrapped_key = Vec::with_capacity(block_count as usize);
+        for _ in 0..block_count {
+            let length = reader.take_u16()? as usize;
+            wrapped_key.push(BigUint::from_bytes_be(reader.take(length)?));
+        }
+        let nonce = reader.take(NONCE_LENGTH)?;
+        let (header, ciphertext) = input.split_at(reader.position);
+
+        let content_key = self.decrypt(&wrapped_key, padding)?;
+        if content_key.len() != CONTENT_KEY_LENGTH {
+            return Err("Decryption failed".into());
+        }
+        let cipher = Aes256Gcm::new(AesKey::<Aes256Gcm>::from_slice(&content_key));
+        cipher
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: header })
+            .map_err(|_| {
+                error!("Authentication of the hybrid ciphertext failed");
+                "Decryption failed".into()
+            })
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], Box<dyn Error>> {
+        let end = self.position + length;
+        if end > self.data.len() {
+            return Err("Hybrid ciphertext is truncated".into());
+        }
+        let slice = &self.data[self.position..end];
+        self.position = end;
+        Ok(slice)
+    }
+
+    fn take_u16(&mut self) -> Result<u16, Box<dyn Error>> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+}
@@ -0,0 +1,111 @@
+use std::error::Error;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use num::BigUint;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use crate::rsa::{CrtParameters, Key, DEFAULT_SALT_BITS};
+
+impl Key {
+    /// The RFC 7638 thumbprint, also used as the `kid` of exported keys
+    pub fn jwk_thumbprint(&self) -> Result<String, Box<dyn Error>> {
+        let public_exponent = self.public_exponent()
+            .ok_or("Private key does not carry its public exponent")?;
+        // Members in lexicographic order without whitespace as required for the hash input
+        let canonical = format!(
+            r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#,
+            encode_integer(public_exponent),
+            encode_integer(&self.modulus)
+        );
+        Ok(URL_SAFE_NO_PAD.encode(Sha256::digest(canonical.as_bytes())))
+    }
+
+    /// Uses the public half of a private key
+    pub fn to_public_jwk(&self) -> Result<Value, Box<dyn Error>> {
+        let public_exponent = self.public_exponent()
+            .ok_or("Private key does not carry its public exponent")?;
+        Ok(json!({
+            "kty": "RSA",
+            "kid": self.jwk_thumbprint()?,
+            "n": encode_integer(&self.modulus),
+            "e": encode_integer(public_exponent)
+        }))
+    }
+
+    pub fn to_private_jwk(&self) -> Result<Value, Box<dyn Error>> {
+        let public_exponent = self.public_exponent
+            .as_ref()
+            .ok_or("Private key does not carry its public exponent")?;
+        let crt = self.crt
+            .as_ref()
+            .ok_or("Private key does not carry its primes")?;
+        Ok(json!({
+            "kty": "RSA",
+            "kid": self.jwk_thumbprint()?,
+            "n": encode_integer(&self.modulus),
+            "e": encode_integer(public_exponent),
+            "d": encode_integer(&self.exponent),
+            "p": encode_integer(&crt.p),
+            "q": encode_integer(&crt.q),
+            "dp": encode_integer(&crt.dp),
+            "dq": encode_integer(&crt.dq),
+            "qi": encode_integer(&crt.qinv)
+        }))
+    }
+
+    pub fn from_jwk(jwk: &Value) -> Result<Self, Box<dyn Error>> {
+        let members = jwk.as_object().ok_or("JWK must be a JSON object")?;
+        if members.get("kty").and_then(Value::as_str) != Some("RSA") {
+            return Err("JWK is not an RSA key".into());
+        }
+        let modulus = decode_member(members, "n")?;
+        let public_exponent = decode_member(members, "e")?;
+        if !members.contains_key("d") {
+            return Ok(Key::new(public_exponent, modulus, DEFAULT_SALT_BITS));
+        }
+        let exponent = decode_member(members, "d")?;
+        let key = Key::new(exponent, modulus, DEFAULT_SALT_BITS).with_public_exponent(public_exponent);
+        if !members.contains_key("p") {
+            return Ok(key);
+        }
+        let crt = CrtParameters {
+            p: decode_member(members, "p")?,
+            q: decode_member(members, "q")?,
+            dp: decode_member(members, "dp")?,
+            dq: decode_member(members, "dq")?,
+            qinv: decode_member(members, "qi")?
+        };
+        Ok(key.with_crt(crt))
+    }
+
+    pub fn from_jwk_json(data: &[u8]) -> Result<Self, Box<dyn Error>> {
+        Self::from_jwk(&serde_json::from_slice(data)?)
+    }
+}
+
+pub fn jwk_set(keys: &[Key]) -> Result<Value, Box<dyn Error>> {
+    Ok(json!({
+        "keys": keys
+            .iter()
+            .map(|i| {
+                i.to_public_jwk()
+            })
+            .collect::<Result<Vec<Value>, Box<dyn Error>>>()?
+    }))
+}
+
+fn encode_integer(value: &BigUint) -> String {
+    URL_SAFE_NO_PAD.encode(value.to_bytes_be())
+}
+
+fn decode_member(members: &Map<String, Value>, name: &str) -> Result<BigUint, Box<dyn Error>> {
+    let encoded = members
+        .get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| format!("JWK is missing the {} member", name))?;
+    let bytes = URL_SAFE_NO_PAD.decode(encoded)?;
+    if bytes.is_empty() {
+        return Err(format!("JWK member {} is empty", name).into());
+    }
+    Ok(BigUint::from_bytes_be(&bytes))
+}
@@ -0,0 +1,169 @@
+use std::error::Error;
+use log::trace;
+use num::{BigUint, Integer, One};
+use rand::{CryptoRng, RngCore};
+use sha2::{Digest, Sha256};
+use crate::math::{modular_inverse, new_prime, new_provable_prime, PrimeCertificate};
+use crate::rsa::protection::{derive_key, SCRYPT_LOG_N, SCRYPT_P, SCRYPT_R};
+use crate::rsa::{CrtParameters, Key, KeySet, DEFAULT_SALT_BITS};
+
+pub const DEFAULT_PUBLIC_EXPONENT: u32 = 65537;
+// Smaller primes would all be rejected by the trial division in is_prime
+const MIN_BIT_LENGTH: u64 = 64;
+const PROVABLE_SEED_LENGTH: usize = 32;
+const MIN_SEED_LENGTH: usize = 16;
+// Fixed because the same passphrase has to give the same key pair on every machine
+const SEED_PASSPHRASE_SALT: &[u8] = b"amh_rsa deterministic key generation";
+
+/// Parameters for generating a new key pair with `KeySet::generate`
+#[derive(Debug, Clone)]
+pub struct KeyGenOptions {
+    pub(crate) salt_bits: u32,
+    pub(crate) bit_length: u64,
+    pub(crate) public_exponent: BigUint,
+    pub(crate) provable: bool
+}
+
+impl Default for KeyGenOptions {
+    fn default() -> Self {
+        KeyGenOptions {
+            salt_bits: DEFAULT_SALT_BITS,
+            bit_length: 4096,
+            public_exponent: BigUint::from(DEFAULT_PUBLIC_EXPONENT),
+            provable: false
+        }
+    }
+}
+
+impl KeyGenOptions {
+    pub fn new(salt_bits: u32, bit_length: u64) -> Self {
+        KeyGenOptions {
+            salt_bits,
+            bit_length,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_public_exponent(mut self, public_exponent: BigUint) -> Self {
+        self.public_exponent = public_exponent;
+        self
+    }
+
+    /// Use Shawe-Taylor provable primes, the key set then carries their certificates
+    pub fn with_provable_primes(mut self, provable: bool) -> Self {
+        self.provable = provable;
+        self
+    }
+
+    /// A ChaCha20 seed for deterministic key generation, the same seed always gives the same key pair
+    pub fn seed_from_hex(hex: &str) -> Result<[u8; 32], Box<dyn Error>> {
+        let hex = hex.trim();
+        if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+            return Err("The seed must be an even number of hex digits".into());
+        }
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|i| {
+                u8::from_str_radix(&hex[i..i + 2], 16)
+            })
+            .collect::<Result<Vec<u8>, _>>()?;
+        if bytes.len() < MIN_SEED_LENGTH {
+            return Err(format!("The seed must be at least {} bytes long", MIN_SEED_LENGTH).into());
+        }
+        Ok(Sha256::digest(bytes).into())
+    }
+
+    pub fn seed_from_passphrase(passphrase: &str) -> Result<[u8; 32], Box<dyn Error>> {
+        derive_key(passphrase, SEED_PASSPHRASE_SALT, SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P)
+    }
+}
+
+impl KeySet {
+    /// Draws all randomness from rng, so a seeded rng always yields the same key pair
+    pub fn generate<R: RngCore + CryptoRng>(rng: &mut R, options: &KeyGenOptions) -> Result<Self, Box<dyn Error>> {
+        let e = &options.public_exponent;
+        if e < &BigUint::from(3u8) || e.is_even() {
+            return Err("The public exponent must be odd and at least 3".into());
+        }
+        if options.bit_length < MIN_BIT_LENGTH {
+            return Err(format!("Keys must be at least {} bits long", MIN_BIT_LENGTH).into());
+        }
+        let mut generator = if options.provable {
+            let mut seed = vec![0u8; PROVABLE_SEED_LENGTH];
+            rng.fill_bytes(&mut seed);
+            PrimeGenerator::Provable(seed)
+        }
+        else {
+            PrimeGenerator::Probable
+        };
+        let (p, p_certificate) = generator.coprime_prime(rng, options.bit_length - options.bit_length / 2, e)?;
+        let (q, q_certificate) = loop {
+            let (q, q_certificate) = generator.coprime_prime(rng, options.bit_length / 2, e)?;
+            if far_apart(&p, &q, options.bit_length) {
+                break (q, q_certificate);
+            }
+            trace!("Prime rejected, p and q are too close together");
+        };
+        let phi = (&p - 1u8) * (&q - 1u8);
+        let d = modular_inverse(e, &phi);
+        let mut keyset = Self::from_parts(p, q, e.clone(), d, options.salt_bits);
+        keyset.certificates = p_certificate.zip(q_certificate);
+        if keyset.bit_length != options.bit_length {
+            return Err(format!("Generated a {} bit modulus instead of {} bits", keyset.bit_length, options.bit_length).into());
+        }
+        Ok(keyset)
+    }
+
+    pub(crate) fn from_parts(p: BigUint, q: BigUint, e: BigUint, d: BigUint, salt_bits: u32) -> Self {
+        let n = &p * &q;
+        let phi = (&p - 1u8) * (&q - 1u8);
+        let crt = CrtParameters::new(p.clone(), q.clone(), &d);
+        KeySet {
+            private_key: Key::new(d, n.clone(), salt_bits)
+                .with_public_exponent(e.clone())
+                .with_crt(crt),
+            public_key: Key::new(e, n.clone(), salt_bits),
+            phi,
+            primes: (p, q),
+            bit_length: n.bits(),
+            certificates: None
+        }
+    }
+}
+
+// Distinct primes whose difference is above the bound from FIPS 186-5 A.1.3, which also rules out p = q
+pub(crate) fn far_apart(p: &BigUint, q: &BigUint, bit_length: u64) -> bool {
+    let distance = if p > q { p - q } else { q - p };
+    distance > BigUint::one() << (bit_length / 2).saturating_sub(100)
+}
+
+enum PrimeGenerator {
+    Probable,
+    // Holds the Shawe-Taylor seed, which carries on from one prime to the next
+    Provable(Vec<u8>)
+}
+
+impl PrimeGenerator {
+    // e is only invertible mod phi when it shares no factor with p - 1 or q - 1,
+    // and the top two bits have to be set for the modulus to reach its full length
+    fn coprime_prime<R: RngCore + CryptoRng>(&mut self, rng: &mut R, bit_length: u64, e: &BigUint) -> Result<(BigUint, Option<PrimeCertificate>), Box<dyn Error>> {
+        loop {
+            let (candidate, certificate) = match self {
+                PrimeGenerator::Probable => (new_prime(rng, bit_length), None),
+                PrimeGenerator::Provable(seed) => {
+                    let (certificate, next_seed) = new_provable_prime(bit_length, seed)?;
+                    *seed = next_seed;
+                    (certificate.prime(), Some(certificate))
+                }
+            };
+            if (&candidate >> (bit_length - 2)) != BigUint::from(3u8) {
+                trace!("Prime rejected, the top two bits are not set");
+                continue;
+            }
+            if (&candidate - 1u8).gcd(e).is_one() {
+                return Ok((candidate, certificate));
+            }
+            trace!("Prime rejected, p - 1 shares a factor with e");
+        }
+    }
+}
@@ -0,0 +1,277 @@
+use std::error::Error;
+use aes::Aes256;
+use aes::cipher::{KeyIvInit, StreamCipher};
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD, STANDARD_NO_PAD};
+use log::{error, trace};
+use num::BigUint;
+use rand::{CryptoRng, RngCore};
+use sha2::{Digest, Sha256};
+use crate::rsa::{pem, CrtParameters, Key, DEFAULT_SALT_BITS};
+
+pub(crate) const OPENSSH_PRIVATE_LABEL: &str = "OPENSSH PRIVATE KEY";
+pub(crate) const SSH_RSA: &str = "ssh-rsa";
+const AUTH_MAGIC: &[u8] = b"openssh-key-v1\0";
+const CIPHER: &str = "aes256-ctr";
+const KDF: &str = "bcrypt";
+const KDF_ROUNDS: u32 = 16;
+// Upper bound for bcrypt rounds read from a file, ssh-keygen -a rarely goes past a few hundred
+const MAX_KDF_ROUNDS: u32 = 1024;
+const KDF_SALT_LENGTH: usize = 16;
+
+impl Key {
+    /// The public key blob used inside OpenSSH keys and for fingerprints
+    pub fn to_ssh_public_blob(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let public_exponent = self.public_exponent()
+            .ok_or("Private key does not carry its public exponent")?;
+        let mut writer = SshWriter::default();
+        writer.string(SSH_RSA.as_bytes());
+        writer.mpint(public_exponent);
+        writer.mpint(&self.modulus);
+        Ok(writer.data)
+    }
+
+    /// The SHA-256 fingerprint of the public key as printed by ssh-keygen
+    pub fn fingerprint(&self) -> Result<String, Box<dyn Error>> {
+        Ok(format!("SHA256:{}", STANDARD_NO_PAD.encode(Sha256::digest(self.to_ssh_public_blob()?))))
+    }
+
+    pub fn to_openssh_public(&self) -> Result<String, Box<dyn Error>> {
+        Ok(format!("{} {}\n", SSH_RSA, STANDARD.encode(self.to_ssh_public_blob()?)))
+    }
+
+    pub fn from_openssh_public(line: &str) -> Result<Self, Box<dyn Error>> {
+        let mut fields = line.split_whitespace();
+        if fields.next() != Some(SSH_RSA) {
+            return Err("Not an ssh-rsa public key".into());
+        }
+        let blob = STANDARD.decode(fields.next().ok_or("Missing public key data")?)?;
+        Self::from_ssh_public_blob(&blob)
+    }
+
+    fn from_ssh_public_blob(blob: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let mut reader = SshReader::new(blob);
+        if reader.string()? != SSH_RSA.as_bytes() {
+            return Err("Not an ssh-rsa public key".into());
+        }
+        let exponent = reader.mpint()?;
+        let modulus = reader.mpint()?;
+        reader.finish()?;
+        Ok(Key::new(exponent, modulus, DEFAULT_SALT_BITS))
+    }
+
+    /// An openssh-key-v1 container, encrypted with bcrypt-pbkdf and AES-256-CTR when a passphrase is given
+    pub fn to_openssh_private<R: RngCore + CryptoRng>(&self, rng: &mut R, passphrase: Option<&str>) -> Result<String, Box<dyn Error>> {
+        let public_key = self.public_key().ok_or("Private key does not carry its public exponent")?;
+        let crt = self.crt.as_ref().ok_or("Private key does not carry its primes")?;
+        let check = rng.next_u32();
+        let mut private = SshWriter::default();
+        private.u32(check);
+        private.u32(check);
+        private.string(SSH_RSA.as_bytes());
+        private.mpint(&self.modulus);
+        private.mpint(&public_key.exponent);
+        private.mpint(&self.exponent);
+        private.mpint(&crt.qinv);
+        private.mpint(&crt.p);
+        private.mpint(&crt.q);
+        private.string(b"");
+        let block_size = if passphrase.is_some() { 16 } else { 8 };
+        let mut padding = 1u8;
+        while private.data.len() % block_size != 0 {
+            private.data.push(padding);
+            padding += 1;
+        }
+
+        let mut writer = SshWriter::default();
+        writer.data.extend_from_slice(AUTH_MAGIC);
+        match passphrase {
+            Some(passphrase) => {
+                let mut salt = [0u8; KDF_SALT_LENGTH];
+                rng.fill_bytes(&mut salt);
+                let mut options = SshWriter::default();
+                options.string(&salt);
+                options.u32(KDF_ROUNDS);
+                writer.string(CIPHER.as_bytes());
+                writer.string(KDF.as_bytes());
+                writer.string(&options.data);
+                apply_cipher(passphrase, &salt, KDF_ROUNDS, &mut private.data)?;
+            }
+            None => {
+                writer.string(b"none");
+                writer.string(b"none");
+                writer.string(b"");
+            }
+        }
+        writer.u32(1);
+        writer.string(&public_key.to_ssh_public_blob()?);
+        writer.string(&private.data);
+        Ok(pem::encode(OPENSSH_PRIVATE_LABEL, &writer.data))
+    }
+
+    pub fn from_openssh_private(data: &[u8], passphrase: Option<&str>) -> Result<Self, Box<dyn Error>> {
+        let container = OpensshContainer::parse(data)?;
+        let mut private = container.private.to_vec();
+        match (container.cipher, passphrase) {
+            (b"none", _) => {}
+            (cipher, Some(passphrase)) if cipher == CIPHER.as_bytes() && container.kdf == KDF.as_bytes() => {
+                let mut options = SshReader::new(container.kdf_options);
+                let salt = options.string()?;
+                let rounds = options.u32()?;
+                options.finish()?;
+                if rounds > MAX_KDF_ROUNDS {
+                    return Err("OpenSSH bcrypt rounds are out of range".into());
+                }
+                trace!("Decrypting OpenSSH key with {} bcrypt rounds", rounds);
+                apply_cipher(passphrase, salt, rounds, &mut private)?;
+            }
+            (cipher, Some(_)) => {
+                return Err(format!("Unsupported OpenSSH cipher {}", String::from_utf8_lossy(cipher)).into());
+            }
+            (_, None) => {
+                return Err("Private key is passphrase protected".into());
+            }
+        }
+
+        let mut reader = SshReader::new(&private);
+        if reader.u32()? != reader.u32()? {
+            error!("OpenSSH check values do not match");
+            return Err("Incorrect passphrase or corrupted key".into());
+        }
+        if reader.string()? != SSH_RSA.as_bytes() {
+            return Err("Not an ssh-rsa private key".into());
+        }
+        let modulus = reader.mpint()?;
+        let public_exponent = reader.mpint()?;
+        let exponent = reader.mpint()?;
+        let qinv = reader.mpint()?;
+        let p = reader.mpint()?;
+        let q = reader.mpint()?;
+        if p < BigUint::from(2u8) || q < BigUint::from(2u8) {
+            return Err("OpenSSH key has invalid primes".into());
+        }
+        let mut crt = CrtParameters::new(p, q, &exponent);
+        crt.qinv = qinv;
+        Ok(Key::new(exponent, modulus, DEFAULT_SALT_BITS)
+            .with_public_exponent(public_exponent)
+            .with_crt(crt))
+    }
+
+    pub(crate) fn is_encrypted_openssh(data: &[u8]) -> bool {
+        OpensshContainer::parse(data).is_ok_and(|i| i.cipher != b"none")
+    }
+}
+
+struct OpensshContainer<'a> {
+    cipher: &'a [u8],
+    kdf: &'a [u8],
+    kdf_options: &'a [u8],
+    private: &'a [u8]
+}
+
+impl<'a> OpensshContainer<'a> {
+    fn parse(data: &'a [u8]) -> Result<Self, Box<dyn Error>> {
+        let body = data
+            .strip_prefix(AUTH_MAGIC)
+            .ok_or("Not an openssh-key-v1 private key")?;
+        let mut reader = SshReader::new(body);
+        let cipher = reader.string()?;
+        let kdf = reader.string()?;
+        let kdf_options = reader.string()?;
+        if reader.u32()? != 1 {
+            return Err("Only OpenSSH files with a single key are supported".into());
+        }
+        reader.string()?;
+        let private = reader.string()?;
+        reader.finish()?;
+        Ok(OpensshContainer {
+            cipher,
+            kdf,
+            kdf_options,
+            private
+        })
+    }
+}
+
+fn apply_cipher(passphrase: &str, salt: &[u8], rounds: u32, data: &mut [u8]) -> Result<(), Box<dyn Error>> {
+    let mut derived = [0u8; 48];
+    bcrypt_pbkdf::bcrypt_pbkdf(passphrase, salt, rounds, &mut derived)
+        .map_err(|_| "Key derivation failed")?;
+    let (key, iv) = derived.split_at(32);
+    let mut cipher = ctr::Ctr128BE::<Aes256>::new_from_slices(key, iv)
+        .map_err(|_| "Invalid cipher parameters")?;
+    cipher.apply_keystream(data);
+    Ok(())
+}
+
+#[derive(Default)]
+struct SshWriter {
+    data: Vec<u8>
+}
+
+impl SshWriter {
+    fn u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn string(&mut self, value: &[u8]) {
+        self.u32(value.len() as u32);
+        self.data.extend_from_slice(value);
+    }
+
+    fn mpint(&mut self, value: &BigUint) {
+        if value == &BigUint::ZERO {
+            return self.string(&[]);
+        }
+        let mut bytes = value.to_bytes_be();
+        if bytes[0] & 0x80 != 0 {
+            bytes.insert(0, 0);
+        }
+        self.string(&bytes);
+    }
+}
+
+struct SshReader<'a> {
+    data: &'a [u8]
+}
+
+impl<'a> SshReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        SshReader { data }
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], Box<dyn Error>> {
+        if length > self.data.len() {
+            return Err("OpenSSH key data is truncated".into());
+        }
+        let (taken, rest) = self.data.split_at(length);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32, Box<dyn Error>> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn string(&mut self) -> Result<&'a [u8], Box<dyn Error>> {
+        let length = self.u32()? as usize;
+        self.take(length)
+    }
+
+    fn mpint(&mut self) -> Result<BigUint, Box<dyn Error>> {
+        let bytes = self.string()?;
+        if bytes.first().is_some_and(|i| i & 0x80 != 0) {
+            return Err("Negative mpint in OpenSSH key".into());
+        }
+        Ok(BigUint::from_bytes_be(bytes))
+    }
+
+    fn finish(&self) -> Result<(), Box<dyn Error>> {
+        if self.data.is_empty() {
+            Ok(())
+        }
+        else {
+            Err("Trailing data in OpenSSH key".into())
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use std::error::Error;
+use log::trace;
+use num::bigint::RandBigInt;
+use num::BigUint;
+use rand::{CryptoRng, RngCore};
+use sha2::{Digest, Sha256};
+use crate::math::i2osp;
+use crate::rsa::Key;
+
+const HASH_LENGTH: usize = 32;
+const PKCS1_MIN_PADDING: usize = 8;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Padding {
+    /// The original scheme, a marker bit followed by the data and `salt_bits` random bits
+    Salted,
+    /// RSAES-OAEP from RFC 8017 with SHA-256 and MGF1
+    Oaep { label: Vec<u8> },
+    /// RSAES-PKCS1-v1_5 from RFC 8017, only for interoperability with older systems
+    Pkcs1v15
+}
+
+impl Default for Padding {
+    fn default() -> Self {
+        Padding::Oaep { label: Vec::new() }
+    }
+}
+
+impl Padding {
+    pub fn max_message_length(&self, key: &Key) -> Result<usize, Box<dyn Error>> {
+        let available = match self {
+            Padding::Salted => {
+                // One marker bit keeps leading zero bytes and the result must stay below the modulus
+                key.modulus.bits().saturating_sub(2 + key.salt_bits as u64) as usize / 8
+            }
+            Padding::Oaep { .. } => {
+                key.modulus_length().saturating_sub(2 * HASH_LENGTH + 2)
+            }
+            Padding::Pkcs1v15 => {
+                key.modulus_length().saturating_sub(PKCS1_MIN_PADDING + 3)
+            }
+        };
+        if available == 0 {
+            Err("Modulus is too small to hold a single byte with this padding".into())
+        }
+        else {
+            Ok(available)
+        }
+    }
+
+    pub(crate) fn encode<R: RngCore + CryptoRng>(&self, rng: &mut R, key: &Key, input: &[u8]) -> Result<BigUint, Box<dyn Error>> {
+        if input.len() > self.max_message_length(key)? {
+            return Err("Message too long".into());
+        }
+        match self {
+            Padding::Salted => {
+                let salt = rng.gen_biguint(key.salt_bits as u64);
+                trace!("Salt: {:b}", &salt);
+                let mut marked = Vec::with_capacity(input.len() + 1);
+                marked.push(1u8);
+                marked.extend_from_slice(input);
+                let input = BigUint::from_bytes_be(&marked);
+                trace!("Input: {:b}", &input);
+                Ok((input << key.salt_bits) | salt)
+            }
+            Padding::Oaep { label } => {
+                let encoded = oaep_encode(rng, input, label, key.modulus_length());
+                Ok(BigUint::from_bytes_be(&encoded))
+            }
+            Padding::Pkcs1v15 => {
+                let encoded = pkcs1v15_encode(rng, input, key.modulus_length());
+                Ok(BigUint::from_bytes_be(&encoded))
+            }
+        }
+    }
+
+    pub(crate) fn decode(&self, key: &Key, input: &BigUint) -> Option<Vec<u8>> {
+        match self {
+            Padding::Salted => {
+                let desalted = (input >> key.salt_bits).to_bytes_be();
+                match desalted.split_first() {
+                    Some((1, block)) => Some(block.to_vec()),
+                    _ => None
+                }
+            }
+            Padding::Oaep { label } => {
+                let encoded = i2osp(input, key.modulus_length())?;
+                oaep_decode(&encoded, label)
+            }
+            Padding::Pkcs1v15 => {
+                let encoded = i2osp(input, key.modulus_length())?;
+                pkcs1v15_decode(&encoded)
+            }
+        }
+    }
+}
+
+fn oaep_encode<R: RngCore + CryptoRng>(rng: &mut R, message: &[u8], label: &[u8], length: usize) -> Vec<u8> {
+    let label_hash = Sha256::digest(label);
+    let db_length = length - HASH_LENGTH - 1;
+    let mut db = vec![0u8; db_length];
+    db[..HASH_LENGTH].copy_from_slice(&label_hash);
+    db[db_length - message.len() - 1] = 1;
+    db[db_length - message.len()..].copy_from_slice(message);
+
+    let mut seed = [0u8; HASH_LENGTH];
+    rng.fill_bytes(&mut seed);
+    xor_in_place(&mut db, &mgf1(&seed, db_length));
+    xor_in_place(&mut seed, &mgf1(&db, HASH_LENGTH));
+
+    let mut encoded = Vec::with_capacity(length);
+    encoded.push(0);
+    encoded.extend_from_slice(&seed);
+    encoded.extend_from_slice(&db);
+    encoded
+}
+
+fn oaep_decode(encoded: &[u8], label: &[u8]) -> Option<Vec<u8>> {
+    if encoded.len() < 2 * HASH_LENGTH + 2 {
+        return None;
+    }
+    let label_hash = Sha256::digest(label);
+    let (masked_seed, masked_db) = encoded[1..].split_at(HASH_LENGTH);
+    let mut seed = masked_seed.to_vec();
+    xor_in_place(&mut seed, &mgf1(masked_db, HASH_LENGTH));
+    let mut db = masked_db.to_vec();
+    xor_in_place(&mut db, &mgf1(&seed, masked_db.len()));
+
+    // Every check is evaluated before deciding so failures are indistinguishable
+    let mut invalid = encoded[0];
+    for (i, j) in db[..HASH_LENGTH].iter().zip(label_hash.iter()) {
+        invalid |= i ^ j;
+    }
+    let mut separator = None;
+    let mut looking = 1u8;
+    for (i, byte) in db.iter().enumerate().skip(HASH_LENGTH) {
+        let is_one = (*byte == 1) as u8;
+        let is_zero = (*byte == 0) as u8;
+        if looking & is_one == 1 {
+            separator = Some(i);
+        }
+        invalid |= looking & (1 ^ is_one) & (1 ^ is_zero);
+        looking &= 1 ^ is_one;
+    }
+    match (invalid, separator) {
+        (0, Some(separator)) => Some(db[separator + 1..].to_vec()),
+        _ => None
+    }
+}
+
+fn pkcs1v15_encode<R: RngCore + CryptoRng>(rng: &mut R, message: &[u8], length: usize) -> Vec<u8> {
+    let mut padding = vec![0u8; length - message.len() - 3];
+    rng.fill_bytes(&mut padding);
+    for i in padding.iter_mut() {
+        while *i == 0 {
+            *i = (rng.next_u32() & 0xff) as u8;
+        }
+    }
+
+    let mut encoded = Vec::with_capacity(length);
+    encoded.extend_from_slice(&[0, 2]);
+    encoded.extend_from_slice(&padding);
+    encoded.push(0);
+    encoded.extend_from_slice(message);
+    encoded
+}
+
+fn pkcs1v15_decode(encoded: &[u8]) -> Option<Vec<u8>> {
+    if encoded.len() < PKCS1_MIN_PADDING + 3 {
+        return None;
+    }
+    // Every check is evaluated before deciding so failures are indistinguishable
+    let mut invalid = encoded[0] | (encoded[1] ^ 2);
+    let mut separator = None;
+    let mut looking = 1u8;
+    for (i, byte) in encoded.iter().enumerate().skip(2) {
+        let is_zero = (*byte == 0) as u8;
+        if looking & is_zero == 1 {
+            separator = Some(i);
+        }
+        looking &= 1 ^ is_zero;
+    }
+    invalid |= looking;
+    let separator = separator.unwrap_or(0);
+    invalid |= (separator < PKCS1_MIN_PADDING + 2) as u8;
+    match invalid {
+        0 => Some(encoded[separator + 1..].to_vec()),
+        _ => None
+    }
+}
+
+pub(crate) fn mgf1(seed: &[u8], length: usize) -> Vec<u8> {
+    let mut mask = Vec::with_capacity(length + HASH_LENGTH);
+    let mut counter = 0u32;
+    while mask.len() < length {
+        let mut hasher = Sha256::new();
+        hasher.update(seed);
+        hasher.update(counter.to_be_bytes());
+        mask.extend_from_slice(&hasher.finalize());
+        counter += 1;
+    }
+    mask.truncate(length);
+    mask
+}
+
+fn xor_in_place(target: &mut [u8], mask: &[u8]) {
+    for (i, j) in target.iter_mut().zip(mask) {
+        *i ^= j;
+    }
+}
@@ -0,0 +1,209 @@
+use std::error::Error;
+use aes::{Aes128, Aes192, Aes256};
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use aes::cipher::block_padding::Pkcs7;
+use aes_gcm::aead::Aead;
+use aes_gcm::{Aes128Gcm, Aes256Gcm, KeyInit, Nonce};
+use log::{error, trace};
+use num::{BigUint, ToPrimitive};
+use rand::{CryptoRng, RngCore};
+use sha2::Sha256;
+use crate::rsa::der::Reader;
+use crate::rsa::{der, pem, Key, DEFAULT_SALT_BITS};
+
+pub(crate) const ENCRYPTED_PKCS8_LABEL: &str = "ENCRYPTED PRIVATE KEY";
+const SALT_LENGTH: usize = 16;
+const IV_LENGTH: usize = 16;
+const ITERATIONS: u32 = 600_000;
+// Upper bound for iteration counts read from a file
+const MAX_ITERATIONS: u32 = 10_000_000;
+
+// 1.2.840.113549.1.5.13
+const PBES2_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x05, 0x0d];
+// 1.2.840.113549.1.5.12
+const PBKDF2_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x05, 0x0c];
+// 1.2.840.113549.2.9
+const HMAC_SHA256_OID: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x02, 0x09];
+// 2.16.840.1.101.3.4.1, the last arc selects the AES variant
+const AES_OID_PREFIX: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x01];
+const AES_128_CBC: u8 = 2;
+const AES_192_CBC: u8 = 22;
+const AES_256_CBC: u8 = 42;
+const AES_128_GCM: u8 = 6;
+const AES_256_GCM: u8 = 46;
+
+impl Key {
+    /// PBES2 encrypted PKCS#8 using PBKDF2-HMAC-SHA256 and AES-256-CBC like OpenSSL
+    pub fn to_encrypted_pkcs8_der<R: RngCore + CryptoRng>(&self, rng: &mut R, passphrase: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut salt = [0u8; SALT_LENGTH];
+        rng.fill_bytes(&mut salt);
+        let mut iv = [0u8; IV_LENGTH];
+        rng.fill_bytes(&mut iv);
+        let mut derived = [0u8; 32];
+        pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), &salt, ITERATIONS, &mut derived);
+        let ciphertext = cbc::Encryptor::<Aes256>::new(&derived.into(), &iv.into())
+            .encrypt_padded_vec_mut::<Pkcs7>(&self.to_pkcs8_der()?);
+
+        let mut aes_oid = AES_OID_PREFIX.to_vec();
+        aes_oid.push(AES_256_CBC);
+        let kdf = der::sequence(&[
+            der::encode(der::TAG_OBJECT_IDENTIFIER, PBKDF2_OID),
+            der::sequence(&[
+                der::encode(der::TAG_OCTET_STRING, &salt),
+                der::integer(&ITERATIONS.into()),
+                der::sequence(&[
+                    der::encode(der::TAG_OBJECT_IDENTIFIER, HMAC_SHA256_OID),
+                    der::encode(der::TAG_NULL, &[])
+                ])
+            ])
+        ]);
+        let scheme = der::sequence(&[
+            der::encode(der::TAG_OBJECT_IDENTIFIER, &aes_oid),
+            der::encode(der::TAG_OCTET_STRING, &iv)
+        ]);
+        Ok(der::sequence(&[
+            der::sequence(&[
+                der::encode(der::TAG_OBJECT_IDENTIFIER, PBES2_OID),
+                der::sequence(&[kdf, scheme])
+            ]),
+            der::encode(der::TAG_OCTET_STRING, &ciphertext)
+        ]))
+    }
+
+    pub fn to_encrypted_pkcs8_pem<R: RngCore + CryptoRng>(&self, rng: &mut R, passphrase: &str) -> Result<String, Box<dyn Error>> {
+        Ok(pem::encode(ENCRYPTED_PKCS8_LABEL, &self.to_encrypted_pkcs8_der(rng, passphrase)?))
+    }
+
+    pub fn from_encrypted_pkcs8_der(data: &[u8], passphrase: &str) -> Result<Self, Box<dyn Error>> {
+        let info = EncryptedPrivateKeyInfo::parse(data)?;
+        trace!("PBES2 with {} PBKDF2 iterations and AES OID arc {}", info.iterations, info.cipher);
+        let plaintext = info.decrypt(passphrase).ok_or_else(|| {
+            error!("Could not decrypt PKCS#8 private key");
+            "Incorrect passphrase or corrupted key"
+        })?;
+        Self::from_pkcs8_der(&plaintext, DEFAULT_SALT_BITS)
+    }
+
+    pub(crate) fn is_encrypted_pkcs8_der(data: &[u8]) -> bool {
+        EncryptedPrivateKeyInfo::parse(data).is_ok()
+    }
+}
+
+struct EncryptedPrivateKeyInfo<'a> {
+    salt: &'a [u8],
+    iterations: u32,
+    key_length: Option<usize>,
+    cipher: u8,
+    iv: &'a [u8],
+    ciphertext: &'a [u8]
+}
+
+impl<'a> EncryptedPrivateKeyInfo<'a> {
+    fn parse(data: &'a [u8]) -> Result<Self, Box<dyn Error>> {
+        let mut outer = Reader::new(data);
+        let mut info = outer.read_sequence()?;
+        outer.finish()?;
+        let mut algorithm = info.read_sequence()?;
+        if algorithm.read(der::TAG_OBJECT_IDENTIFIER)? != PBES2_OID {
+            return Err("Only PBES2 encrypted private keys are supported".into());
+        }
+        let mut params = algorithm.read_sequence()?;
+        algorithm.finish()?;
+
+        let mut kdf = params.read_sequence()?;
+        if kdf.read(der::TAG_OBJECT_IDENTIFIER)? != PBKDF2_OID {
+            return Err("Only PBKDF2 key derivation is supported".into());
+        }
+        let mut kdf_params = kdf.read_sequence()?;
+        kdf.finish()?;
+        let salt = kdf_params.read(der::TAG_OCTET_STRING)?;
+        let iterations = kdf_params.read_integer()?
+            .to_u32()
+            .filter(|i| (1..=MAX_ITERATIONS).contains(i))
+            .ok_or("PBKDF2 iteration count is out of range")?;
+        let mut key_length = None;
+        let mut prf_found = false;
+        while !kdf_params.is_empty() {
+            if kdf_params.peek_tag() == Some(der::TAG_INTEGER) {
+                key_length = kdf_params.read_integer()?.to_usize();
+            }
+            else {
+                let mut prf = kdf_params.read_sequence()?;
+                if prf.read(der::TAG_OBJECT_IDENTIFIER)? != HMAC_SHA256_OID {
+                    return Err("Only HMAC-SHA256 is supported as the PBKDF2 PRF".into());
+                }
+                prf_found = true;
+            }
+        }
+        if !prf_found {
+            return Err("PBKDF2 with the default HMAC-SHA1 PRF is not supported".into());
+        }
+
+        let mut scheme = params.read_sequence()?;
+        params.finish()?;
+        let cipher = match scheme.read(der::TAG_OBJECT_IDENTIFIER)? {
+            [prefix @ .., arc] if prefix == AES_OID_PREFIX => *arc,
+            _ => return Err("Only AES encrypted private keys are supported".into())
+        };
+        let iv = match cipher {
+            AES_128_CBC | AES_192_CBC | AES_256_CBC => scheme.read(der::TAG_OCTET_STRING)?,
+            AES_128_GCM | AES_256_GCM => {
+                let mut gcm = scheme.read_sequence()?;
+                let nonce = gcm.read(der::TAG_OCTET_STRING)?;
+                // The tag length defaults to 12 when absent, only full 16 byte tags are supported
+                if gcm.is_empty() || gcm.read_integer()? != BigUint::from(16u8) {
+                    return Err("Only 16 byte GCM tags are supported".into());
+                }
+                nonce
+            }
+            _ => return Err(format!("Unsupported AES mode {}", cipher).into())
+        };
+        scheme.finish()?;
+        let ciphertext = info.read(der::TAG_OCTET_STRING)?;
+        info.finish()?;
+        Ok(EncryptedPrivateKeyInfo {
+            salt,
+            iterations,
+            key_length,
+            cipher,
+            iv,
+            ciphertext
+        })
+    }
+
+    fn decrypt(&self, passphrase: &str) -> Option<Vec<u8>> {
+        let expected_length = match self.cipher {
+            AES_128_CBC | AES_128_GCM => 16,
+            AES_192_CBC => 24,
+            _ => 32
+        };
+        if self.key_length.is_some_and(|i| i != expected_length) {
+            return None;
+        }
+        let mut derived = vec![0u8; expected_length];
+        pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), self.salt, self.iterations, &mut derived);
+        match self.cipher {
+            AES_128_CBC => cbc::Decryptor::<Aes128>::new_from_slices(&derived, self.iv)
+                .ok()?
+                .decrypt_padded_vec_mut::<Pkcs7>(self.ciphertext)
+                .ok(),
+            AES_192_CBC => cbc::Decryptor::<Aes192>::new_from_slices(&derived, self.iv)
+                .ok()?
+                .decrypt_padded_vec_mut::<Pkcs7>(self.ciphertext)
+                .ok(),
+            AES_256_CBC => cbc::Decryptor::<Aes256>::new_from_slices(&derived, self.iv)
+                .ok()?
+                .decrypt_padded_vec_mut::<Pkcs7>(self.ciphertext)
+                .ok(),
+            AES_128_GCM if self.iv.len() == 12 => Aes128Gcm::new_from_slice(&derived)
+                .ok()?
+                .decrypt(Nonce::from_slice(self.iv), self.ciphertext)
+                .ok(),
+            AES_256_GCM if self.iv.len() == 12 => Aes256Gcm::new_from_slice(&derived)
+                .ok()?
+                .decrypt(Nonce::from_slice(self.iv), self.ciphertext)
+                .ok(),
+            _ => None
+        }
+    }
+}
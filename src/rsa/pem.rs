@@ -0,0 +1,59 @@
+use std::error::Error;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+
+const LINE_LENGTH: usize = 64;
+
+pub fn encode(label: &str, der: &[u8]) -> String {
+    let body = STANDARD.encode(der);
+    let mut pem = format!("-----BEGIN {}-----\n", label);
+    for i in body.as_bytes().chunks(LINE_LENGTH) {
+        pem.push_str(std::str::from_utf8(i).unwrap());
+        pem.push('\n');
+    }
+    pem.push_str(&format!("-----END {}-----\n", label));
+    pem
+}
+
+pub fn decode(text: &str) -> Result<(String, Vec<u8>), Box<dyn Error>> {
+    let mut lines = text
+        .lines()
+        .map(str::trim)
+        .skip_while(|i| !i.starts_with("-----BEGIN "));
+    let label = lines
+        .next()
+        .and_then(|i| i.strip_prefix("-----BEGIN "))
+        .and_then(|i| i.strip_suffix("-----"))
+        .ok_or("No PEM header found")?
+        .to_string();
+    let end = format!("-----END {}-----", label);
+    let mut body = String::new();
+    let mut terminated = false;
+    for i in lines {
+        if i == end {
+            terminated = true;
+            break;
+        }
+        if i.contains(':') {
+            return Err("PEM headers are not supported".into());
+        }
+        body.push_str(i);
+    }
+    if !terminated {
+        return Err(format!("Missing PEM footer for {}", label).into());
+    }
+    Ok((label, STANDARD.decode(body)?))
+}
+
+pub fn is_pem(data: &[u8]) -> bool {
+    std::str::from_utf8(data).is_ok_and(|i| i.contains("-----BEGIN "))
+}
+
+pub fn decode_label(data: &[u8]) -> Option<&str> {
+    std::str::from_utf8(data)
+        .ok()?
+        .lines()
+        .find_map(|i| {
+            i.trim().strip_prefix("-----BEGIN ")?.strip_suffix("-----")
+        })
+}
@@ -0,0 +1,111 @@
+use std::error::Error;
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+use aes_gcm::Key as AesKey;
+use log::{error, trace};
+use rand::{CryptoRng, RngCore};
+use crate::rsa::{pem, Key, KeyFormat};
+use crate::rsa::openssh::OPENSSH_PRIVATE_LABEL;
+use crate::rsa::pbes2::ENCRYPTED_PKCS8_LABEL;
+
+pub(crate) const PROTECTED_LABEL: &str = "AMH RSA ENCRYPTED PRIVATE KEY";
+const VERSION: u8 = 1;
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+const HEADER_LENGTH: usize = 10 + SALT_LENGTH + NONCE_LENGTH;
+
+// scrypt cost parameters, N = 2^15 uses 32 MiB per derivation
+pub(crate) const SCRYPT_LOG_N: u8 = 15;
+pub(crate) const SCRYPT_R: u32 = 8;
+pub(crate) const SCRYPT_P: u32 = 1;
+
+// Upper bounds for parameters read from a file, N = 2^20 with r = 8 already needs 1 GiB
+const MAX_SCRYPT_LOG_N: u8 = 20;
+const MAX_SCRYPT_R: u32 = 8;
+const MAX_SCRYPT_P: u32 = 16;
+
+impl Key {
+    /// Wraps the private key in the given format with a key derived from the passphrase by scrypt
+    pub fn protect<R: RngCore + CryptoRng>(&self, rng: &mut R, passphrase: &str, format: KeyFormat) -> Result<String, Box<dyn Error>> {
+        let mut salt = [0u8; SALT_LENGTH];
+        rng.fill_bytes(&mut salt);
+        let mut nonce = [0u8; NONCE_LENGTH];
+        rng.fill_bytes(&mut nonce);
+
+        let mut header = vec![VERSION, SCRYPT_LOG_N];
+        header.extend_from_slice(&SCRYPT_R.to_be_bytes());
+        header.extend_from_slice(&SCRYPT_P.to_be_bytes());
+        header.extend_from_slice(&salt);
+        header.extend_from_slice(&nonce);
+
+        let derived = derive_key(passphrase, &salt, SCRYPT_LOG_N, SCRYPT_R, SCRYPT_P)?;
+        let cipher = Aes256Gcm::new(AesKey::<Aes256Gcm>::from_slice(&derived));
+        let plaintext = self.export_private(rng, format)?;
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: &plaintext, aad: &header })
+            .map_err(|_| "Encryption failed")?;
+        header.extend_from_slice(&ciphertext);
+        Ok(pem::encode(PROTECTED_LABEL, &header))
+    }
+
+    /// Also accepts PBES2 encrypted PKCS#8 keys in PEM or DER and encrypted OpenSSH keys
+    pub fn unprotect(data: &[u8], passphrase: &str) -> Result<Self, Box<dyn Error>> {
+        if !pem::is_pem(data) {
+            return Self::from_encrypted_pkcs8_der(data, passphrase);
+        }
+        let (label, body) = pem::decode(std::str::from_utf8(data)?)?;
+        if label == ENCRYPTED_PKCS8_LABEL {
+            return Self::from_encrypted_pkcs8_der(&body, passphrase);
+        }
+        if label == OPENSSH_PRIVATE_LABEL {
+            return Self::from_openssh_private(&body, Some(passphrase));
+        }
+        if label != PROTECTED_LABEL {
+            return Err("Key is not passphrase protected".into());
+        }
+        if body.len() < HEADER_LENGTH || body[0] != VERSION {
+            return Err("Malformed passphrase protected key".into());
+        }
+        let (header, ciphertext) = body.split_at(HEADER_LENGTH);
+        let log_n = header[1];
+        let r = u32::from_be_bytes(header[2..6].try_into()?);
+        let p = u32::from_be_bytes(header[6..10].try_into()?);
+        let salt = &header[10..10 + SALT_LENGTH];
+        let nonce = &header[10 + SALT_LENGTH..];
+        trace!("Deriving key with scrypt log_n={} r={} p={}", log_n, r, p);
+        if log_n > MAX_SCRYPT_LOG_N || r > MAX_SCRYPT_R || p > MAX_SCRYPT_P {
+            error!("scrypt parameters exceed the supported limits");
+            return Err("Malformed passphrase protected key".into());
+        }
+
+        let derived = derive_key(passphrase, salt, log_n, r, p)?;
+        let cipher = Aes256Gcm::new(AesKey::<Aes256Gcm>::from_slice(&derived));
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: header })
+            .map_err(|_| {
+                error!("Could not unlock private key");
+                "Incorrect passphrase or corrupted key"
+            })?;
+        Self::parse(&plaintext)
+    }
+
+    pub fn is_protected(data: &[u8]) -> bool {
+        match pem::decode_label(data) {
+            Some(OPENSSH_PRIVATE_LABEL) => {
+                pem::decode(std::str::from_utf8(data).unwrap_or_default())
+                    .is_ok_and(|(_, body)| Self::is_encrypted_openssh(&body))
+            }
+            Some(label) => label == PROTECTED_LABEL || label == ENCRYPTED_PKCS8_LABEL,
+            None => Self::is_encrypted_pkcs8_der(data)
+        }
+    }
+}
+
+pub(crate) fn derive_key(passphrase: &str, salt: &[u8], log_n: u8, r: u32, p: u32) -> Result<[u8; 32], Box<dyn Error>> {
+    let params = scrypt::Params::new(log_n, r, p, 32)
+        .map_err(|_| "Invalid scrypt parameters")?;
+    let mut derived = [0u8; 32];
+    scrypt::scrypt(passphrase.as_bytes(), salt, &params, &mut derived)
+        .map_err(|_| "Key derivation failed")?;
+    Ok(derived)
+}
@@ -0,0 +1,205 @@
+use std::error::Error;
+use log::{error, trace};
+use num::BigUint;
+use rand::{CryptoRng, RngCore};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use crate::math::i2osp;
+use crate::rsa::Key;
+use crate::rsa::padding::mgf1;
+
+const HASH_LENGTH: usize = 32;
+const PSS_TRAILER: u8 = 0xbc;
+const PKCS1_MIN_PADDING: usize = 8;
+
+const SHA256_DIGEST_INFO: &[u8] = &[
+    0x30, 0x31, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01, 0x05, 0x00, 0x04, 0x20
+];
+const SHA384_DIGEST_INFO: &[u8] = &[
+    0x30, 0x41, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x02, 0x05, 0x00, 0x04, 0x30
+];
+const SHA512_DIGEST_INFO: &[u8] = &[
+    0x30, 0x51, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x03, 0x05, 0x00, 0x04, 0x40
+];
+
+#[derive(clap::ValueEnum, Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Sha384,
+    Sha512
+}
+
+impl HashAlgorithm {
+    pub fn digest(&self, message: &[u8]) -> Vec<u8> {
+        match self {
+            HashAlgorithm::Sha256 => Sha256::digest(message).to_vec(),
+            HashAlgorithm::Sha384 => Sha384::digest(message).to_vec(),
+            HashAlgorithm::Sha512 => Sha512::digest(message).to_vec()
+        }
+    }
+
+    fn digest_info_prefix(&self) -> &'static [u8] {
+        match self {
+            HashAlgorithm::Sha256 => SHA256_DIGEST_INFO,
+            HashAlgorithm::Sha384 => SHA384_DIGEST_INFO,
+            HashAlgorithm::Sha512 => SHA512_DIGEST_INFO
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum SignatureScheme {
+    /// RSASSA-PSS from RFC 8017 with SHA-256 and MGF1
+    Pss { salt_length: usize },
+    /// Deterministic RSASSA-PKCS1-v1_5 from RFC 8017
+    Pkcs1v15 { hash: HashAlgorithm }
+}
+
+impl Default for SignatureScheme {
+    fn default() -> Self {
+        SignatureScheme::Pss { salt_length: HASH_LENGTH }
+    }
+}
+
+impl Key {
+    pub fn sign<R: RngCore + CryptoRng>(&self, rng: &mut R, message: &[u8], scheme: &SignatureScheme) -> Result<Vec<u8>, Box<dyn Error>> {
+        match scheme {
+            SignatureScheme::Pss { salt_length } => self.sign_pss(rng, message, *salt_length),
+            SignatureScheme::Pkcs1v15 { hash } => self.sign_pkcs1v15(*hash, message)
+        }
+    }
+
+    pub fn verify(&self, message: &[u8], signature: &[u8], scheme: &SignatureScheme) -> Result<(), Box<dyn Error>> {
+        match scheme {
+            SignatureScheme::Pss { salt_length } => self.verify_pss(message, signature, *salt_length),
+            SignatureScheme::Pkcs1v15 { hash } => self.verify_pkcs1v15(*hash, message, signature)
+        }
+    }
+
+    pub fn sign_pkcs1v15(&self, hash: HashAlgorithm, message: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        let encoded = pkcs1v15_encode(hash, message, self.modulus_length())?;
+        trace!("PKCS#1 v1.5 encoded message: {:?}", &encoded);
+        let signature = self.apply(&BigUint::from_bytes_be(&encoded))?;
+        i2osp(&signature, self.modulus_length()).ok_or_else(|| "Signing failed".into())
+    }
+
+    pub fn verify_pkcs1v15(&self, hash: HashAlgorithm, message: &[u8], signature: &[u8]) -> Result<(), Box<dyn Error>> {
+        // The encoding is rebuilt and compared as a whole so no alternative encodings are accepted
+        let expected = pkcs1v15_encode(hash, message, self.modulus_length())?;
+        match self.open_signature(signature, self.modulus_length()) {
+            Some(encoded) if encoded == expected => {
+                Ok(())
+            }
+            _ => {
+                error!("PKCS#1 v1.5 signature did not verify");
+                Err("Signature verification failed".into())
+            }
+        }
+    }
+
+    pub fn sign_pss<R: RngCore + CryptoRng>(&self, rng: &mut R, message: &[u8], salt_length: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut salt = vec![0u8; salt_length];
+        rng.fill_bytes(&mut salt);
+        let em_bits = self.modulus.bits() as usize - 1;
+        let encoded = pss_encode(&Sha256::digest(message), &salt, em_bits)?;
+        trace!("PSS encoded message: {:?}", &encoded);
+        let signature = self.apply(&BigUint::from_bytes_be(&encoded))?;
+        i2osp(&signature, self.modulus_length()).ok_or_else(|| "Signing failed".into())
+    }
+
+    pub fn verify_pss(&self, message: &[u8], signature: &[u8], salt_length: usize) -> Result<(), Box<dyn Error>> {
+        let em_bits = self.modulus.bits() as usize - 1;
+        let valid = self
+            .open_signature(signature, em_bits.div_ceil(8))
+            .is_some_and(|i| {
+                pss_verify(&Sha256::digest(message), &i, em_bits, salt_length)
+            });
+        if valid {
+            Ok(())
+        }
+        else {
+            error!("PSS signature did not verify");
+            Err("Signature verification failed".into())
+        }
+    }
+
+    fn open_signature(&self, signature: &[u8], length: usize) -> Option<Vec<u8>> {
+        if signature.len() != self.modulus_length() {
+            return None;
+        }
+        let opened = self.apply(&BigUint::from_bytes_be(signature)).ok()?;
+        i2osp(&opened, length)
+    }
+}
+
+fn pkcs1v15_encode(hash: HashAlgorithm, message: &[u8], em_length: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+    let prefix = hash.digest_info_prefix();
+    let digest = hash.digest(message);
+    let t_length = prefix.len() + digest.len();
+    if em_length < t_length + PKCS1_MIN_PADDING + 3 {
+        return Err("Modulus is too small for the requested hash".into());
+    }
+    let mut encoded = Vec::with_capacity(em_length);
+    encoded.extend_from_slice(&[0, 1]);
+    encoded.resize(em_length - t_length - 1, 0xff);
+    encoded.push(0);
+    encoded.extend_from_slice(prefix);
+    encoded.extend_from_slice(&digest);
+    Ok(encoded)
+}
+
+fn pss_encode(message_hash: &[u8], salt: &[u8], em_bits: usize) -> Result<Vec<u8>, Box<dyn Error>> {
+    let em_length = em_bits.div_ceil(8);
+    if em_length < HASH_LENGTH + salt.len() + 2 {
+        return Err("Modulus is too small for the requested salt length".into());
+    }
+    let hash = pss_hash(message_hash, salt);
+    let db_length = em_length - HASH_LENGTH - 1;
+    let mut db = vec![0u8; db_length];
+    db[db_length - salt.len() - 1] = 1;
+    db[db_length - salt.len()..].copy_from_slice(salt);
+    for (i, j) in db.iter_mut().zip(mgf1(&hash, db_length)) {
+        *i ^= j;
+    }
+    db[0] &= 0xff >> (8 * em_length - em_bits);
+
+    let mut encoded = db;
+    encoded.extend_from_slice(&hash);
+    encoded.push(PSS_TRAILER);
+    Ok(encoded)
+}
+
+fn pss_verify(message_hash: &[u8], encoded: &[u8], em_bits: usize, salt_length: usize) -> bool {
+    let em_length = em_bits.div_ceil(8);
+    if em_length < HASH_LENGTH + salt_length + 2 || encoded.len() != em_length {
+        return false;
+    }
+    if encoded[em_length - 1] != PSS_TRAILER {
+        return false;
+    }
+    let (masked_db, hash) = encoded[..em_length - 1].split_at(em_length - HASH_LENGTH - 1);
+    let top_mask = !(0xffu8 >> (8 * em_length - em_bits));
+    if masked_db[0] & top_mask != 0 {
+        return false;
+    }
+    let mut db: Vec<u8> = masked_db
+        .iter()
+        .zip(mgf1(hash, masked_db.len()))
+        .map(|(i, j)| i ^ j)
+        .collect();
+    db[0] &= !top_mask;
+    let padding_length = em_length - HASH_LENGTH - salt_length - 2;
+    if db[..padding_length].iter().any(|i| *i != 0) || db[padding_length] != 1 {
+        return false;
+    }
+    let salt = &db[db.len() - salt_length..];
+    pss_hash(message_hash, salt).as_slice() == hash
+}
+
+fn pss_hash(message_hash: &[u8], salt: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update([0u8; 8]);
+    hasher.update(message_hash);
+    hasher.update(salt);
+    hasher.finalize().to_vec()
+}
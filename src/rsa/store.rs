@@ -0,0 +1,226 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs;
+use std::fs::{DirBuilder, OpenOptions};
+use std::io;
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::process;
+#[cfg(unix)]
+use std::sync::Once;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use log::trace;
+use crate::rsa::KeySet;
+
+const PUBLIC_EXTENSION: &str = ".pub";
+
+static TEMPORARY_FILES: AtomicUsize = AtomicUsize::new(0);
+#[cfg(unix)]
+static SHARED_ROOT_WARNING: Once = Once::new();
+
+/// A key pair in the key store and which of its halves are present
+#[derive(Debug, PartialEq)]
+pub struct StoredKey {
+    pub name: String,
+    pub has_public: bool,
+    pub has_private: bool
+}
+
+impl KeySet {
+    pub fn list_keys() -> Result<Vec<StoredKey>, Box<dyn Error>> {
+        let mut keys: BTreeMap<String, StoredKey> = BTreeMap::new();
+        for entry in fs::read_dir(Self::get_key_root()?)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            let file_name = match entry.file_name().into_string() {
+                Ok(file_name) => file_name,
+                Err(_) => continue
+            };
+            if file_name.starts_with('.') {
+                continue;
+            }
+            let (name, public) = match file_name.strip_suffix(PUBLIC_EXTENSION) {
+                Some(name) => (name.to_string(), true),
+                None => (file_name, false)
+            };
+            let key = keys.entry(name.clone()).or_insert(StoredKey {
+                name,
+                has_public: false,
+                has_private: false
+            });
+            if public {
+                key.has_public = true;
+            }
+            else {
+                key.has_private = true;
+            }
+        }
+        Ok(keys.into_values().collect())
+    }
+
+    pub fn key_exists(name: &str) -> Result<bool, Box<dyn Error>> {
+        Ok(Self::key_files(name)?.iter().any(|i| i.exists()))
+    }
+
+    pub fn delete_keys(name: &str) -> Result<(), Box<dyn Error>> {
+        for path in Self::existing_key_files(name)? {
+            trace!("Deleting {}", path.display());
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    pub fn rename_keys(name: &str, new_name: &str) -> Result<(), Box<dyn Error>> {
+        for (from, to) in Self::key_file_moves(name, new_name)? {
+            trace!("Renaming {} to {}", from.display(), to.display());
+            // A link fails where a rename would replace a file created since the check above
+            match fs::hard_link(&from, &to) {
+                Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {
+                    return Err(format!("A key named {} already exists", new_name).into());
+                }
+                result => result?
+            }
+            fs::remove_file(from)?;
+        }
+        Ok(())
+    }
+
+    pub fn copy_keys(name: &str, new_name: &str) -> Result<(), Box<dyn Error>> {
+        for (from, to) in Self::key_file_moves(name, new_name)? {
+            trace!("Copying {} to {}", from.display(), to.display());
+            // Written like a new key so a file created since the check above is never clobbered
+            let private = !from.to_string_lossy().ends_with(PUBLIC_EXTENSION);
+            write_key_file(&to, &fs::read(&from)?, private, false)?;
+        }
+        Ok(())
+    }
+
+    pub fn check_key_name(name: &str) -> Result<(), Box<dyn Error>> {
+        if name.contains(['/', '\\']) {
+            return Err(format!("Invalid key name {}, a name with a path separator refers to a file outside the key store", name).into());
+        }
+        if name.is_empty() || name.starts_with('.') || name.ends_with(PUBLIC_EXTENSION) {
+            return Err(format!("Invalid key name {}", name).into());
+        }
+        Ok(())
+    }
+
+    /// The file holding one half of a stored key pair, every read and write of the key store resolves names here
+    pub(crate) fn store_path(name: &str, public: bool) -> Result<PathBuf, Box<dyn Error>> {
+        Self::check_key_name(name)?;
+        let key_root = Self::get_key_root()?;
+        if public {
+            Ok(key_root.join(format!("{}{}", name, PUBLIC_EXTENSION)))
+        }
+        else {
+            Ok(key_root.join(name))
+        }
+    }
+
+    fn key_files(name: &str) -> Result<[PathBuf; 2], Box<dyn Error>> {
+        Ok([Self::store_path(name, false)?, Self::store_path(name, true)?])
+    }
+
+    fn existing_key_files(name: &str) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        let files = Self::key_files(name)?
+            .into_iter()
+            .filter(|i| {
+                i.exists()
+            })
+            .collect::<Vec<PathBuf>>();
+        if files.is_empty() {
+            return Err(format!("No key named {}", name).into());
+        }
+        Ok(files)
+    }
+
+    // Never overwrites, a destination with either half of a key pair is an error
+    fn key_file_moves(name: &str, new_name: &str) -> Result<Vec<(PathBuf, PathBuf)>, Box<dyn Error>> {
+        let targets = Self::key_files(new_name)?;
+        if targets.iter().any(|i| i.exists()) {
+            return Err(format!("A key named {} already exists", new_name).into());
+        }
+        let sources = Self::key_files(name)?;
+        let moves = sources
+            .into_iter()
+            .zip(targets)
+            .filter(|(from, _)| {
+                from.exists()
+            })
+            .collect::<Vec<(PathBuf, PathBuf)>>();
+        if moves.is_empty() {
+            return Err(format!("No key named {}", name).into());
+        }
+        Ok(moves)
+    }
+}
+
+/// Writes a key file via a temporary file so a key is never left half written. Without force the
+/// temporary file is hard linked into place, which fails rather than replacing a file created meanwhile.
+pub fn write_key_file(path: &Path, data: &[u8], private: bool, force: bool) -> Result<(), Box<dyn Error>> {
+    let exists_error = || format!("{} already exists, refusing to overwrite it", path.display());
+    if !force && path.exists() {
+        return Err(exists_error().into());
+    }
+    let file_name = path.file_name().ok_or("Key file path has no file name")?;
+    let mut temporary_name = OsString::from(".");
+    temporary_name.push(file_name);
+    temporary_name.push(format!(".{}.{}.tmp", process::id(), TEMPORARY_FILES.fetch_add(1, Ordering::Relaxed)));
+    let temporary_path = path.with_file_name(temporary_name);
+    trace!("Writing {} via {}", path.display(), temporary_path.display());
+    let result = write_new_file(&temporary_path, data, private)
+        .and_then(|_| {
+            if force {
+                fs::rename(&temporary_path, path)
+            }
+            else {
+                fs::hard_link(&temporary_path, path)
+            }
+        });
+    if result.is_err() || !force {
+        let _ = fs::remove_file(&temporary_path);
+    }
+    match result {
+        Err(error) if error.kind() == io::ErrorKind::AlreadyExists => Err(exists_error().into()),
+        result => Ok(result?)
+    }
+}
+
+#[cfg_attr(not(unix), allow(unused_variables))]
+fn write_new_file(path: &Path, data: &[u8], private: bool) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(if private { 0o600 } else { 0o644 });
+    let mut file = options.open(path)?;
+    file.write_all(data)?;
+    file.sync_all()
+}
+
+/// Creates a missing key root for its owner only. An existing directory may be shared on purpose,
+/// so other users having access to it is only warned about and its permissions are left alone.
+pub(crate) fn prepare_key_root(key_root: &Path) -> Result<(), Box<dyn Error>> {
+    if !key_root.exists() {
+        return Ok(create_key_root(key_root)?);
+    }
+    #[cfg(unix)]
+    if fs::metadata(key_root)?.permissions().mode() & 0o077 != 0 {
+        SHARED_ROOT_WARNING.call_once(|| {
+            eprintln!("Warning: other users can access the key store {}", key_root.display());
+        });
+    }
+    Ok(())
+}
+
+fn create_key_root(key_root: &Path) -> io::Result<()> {
+    let mut builder = DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    builder.mode(0o700);
+    builder.create(key_root)
+}
@@ -0,0 +1,106 @@
+use std::error::Error;
+use std::fmt;
+use num::{Integer, One};
+use rand::{CryptoRng, RngCore};
+use crate::math::is_prime;
+use crate::rsa::keygen::far_apart;
+use crate::rsa::KeySet;
+
+/// A single property of a key pair checked by `KeySet::validate`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ValidationCheck {
+    PrimeP,
+    PrimeQ,
+    Modulus,
+    PrivateExponent,
+    CrtExponentP,
+    CrtExponentQ,
+    CrtCoefficient,
+    PrimeDistance
+}
+
+impl fmt::Display for ValidationCheck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let description = match self {
+            ValidationCheck::PrimeP => "p is not prime",
+            ValidationCheck::PrimeQ => "q is not prime",
+            ValidationCheck::Modulus => "n is not p * q",
+            ValidationCheck::PrivateExponent => "e * d is not 1 mod lambda(n)",
+            ValidationCheck::CrtExponentP => "dP is not d mod (p - 1)",
+            ValidationCheck::CrtExponentQ => "dQ is not d mod (q - 1)",
+            ValidationCheck::CrtCoefficient => "qInv is not the inverse of q mod p",
+            ValidationCheck::PrimeDistance => "p and q are too close together"
+        };
+        write!(f, "{}", description)
+    }
+}
+
+/// The checks a key pair failed, returned as the error of `KeySet::validate`
+#[derive(Debug, PartialEq)]
+pub struct ValidationReport {
+    pub failures: Vec<ValidationCheck>
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let failures = self.failures
+            .iter()
+            .map(|i| {
+                i.to_string()
+            })
+            .collect::<Vec<String>>();
+        write!(f, "Invalid key: {}", failures.join(", "))
+    }
+}
+
+impl Error for ValidationReport {}
+
+impl KeySet {
+    pub fn validate<R: RngCore + CryptoRng>(&self, rng: &mut R) -> Result<(), ValidationReport> {
+        let (p, q) = &self.primes;
+        let n = &self.private_key.modulus;
+        let d = &self.private_key.exponent;
+        let e = &self.public_key.exponent;
+        let mut failures = Vec::new();
+
+        if !is_prime(rng, p) {
+            failures.push(ValidationCheck::PrimeP);
+        }
+        if !is_prime(rng, q) {
+            failures.push(ValidationCheck::PrimeQ);
+        }
+        if &(p * q) != n {
+            failures.push(ValidationCheck::Modulus);
+        }
+        if p.bits() <= 1 || q.bits() <= 1 {
+            // None of the remaining checks are meaningful without proper primes
+            return Err(ValidationReport { failures });
+        }
+
+        let lambda = (p - 1u8).lcm(&(q - 1u8));
+        if !((e * d) % &lambda).is_one() {
+            failures.push(ValidationCheck::PrivateExponent);
+        }
+        if let Some(crt) = &self.private_key.crt {
+            if crt.dp != d % (p - 1u8) {
+                failures.push(ValidationCheck::CrtExponentP);
+            }
+            if crt.dq != d % (q - 1u8) {
+                failures.push(ValidationCheck::CrtExponentQ);
+            }
+            if !((&crt.qinv * q) % p).is_one() {
+                failures.push(ValidationCheck::CrtCoefficient);
+            }
+        }
+        if !far_apart(p, q, n.bits()) {
+            failures.push(ValidationCheck::PrimeDistance);
+        }
+
+        if failures.is_empty() {
+            Ok(())
+        }
+        else {
+            Err(ValidationReport { failures })
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+fn rsa(key_dir: &Path, arguments: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_rsa"))
+        .arg("--key-dir")
+        .arg(key_dir)
+        .args(arguments)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    output
+}
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("amh_rsa_cli_{}_{}", name, std::process::id()));
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+#[test]
+fn test_stdout_roundtrip() {
+    let directory = scratch_dir("roundtrip");
+    let key_dir = directory.join("keys");
+    let input = directory.join("input.bin");
+    let plaintext = (0..=255u8).cycle().take(1000).collect::<Vec<u8>>();
+    fs::write(&input, &plaintext).unwrap();
+    rsa(&key_dir, &["keygen", "-k", "cli", "-b", "1024"]);
+
+    for hybrid in [false, true] {
+        let mode: &[&str] = if hybrid { &["--hybrid"] } else { &[] };
+        let ciphertext = directory.join("ciphertext");
+        let encrypted = rsa(&key_dir, &[&["encrypt", "-i", input.to_str().unwrap(), "-k", "cli"], mode].concat());
+        fs::write(&ciphertext, encrypted.stdout).unwrap();
+        let decrypted = rsa(&key_dir, &[&["decrypt", "-i", ciphertext.to_str().unwrap(), "-k", "cli"], mode].concat());
+        assert_eq!(decrypted.stdout, plaintext);
+    }
+    fs::remove_dir_all(directory).unwrap();
+}